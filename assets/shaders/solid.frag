@@ -0,0 +1,8 @@
+#version 140
+
+out vec4 frag_color;
+uniform vec4 color;
+
+void main() {
+    frag_color = color;
+}
@@ -1,16 +1,30 @@
 //! Main entry point for the game. It manages the game loop.
 
 use glium::Display;
-use glium::glutin::{Event, VirtualKeyCode};
+use glium::glutin::{Event, MonitorId, VirtualKeyCode};
 use std::time::{Duration, Instant};
 
 use config::Config;
-use graphics::Quad;
+#[cfg(feature = "dev-tools")]
+use debug::Toggles;
+use graphics::{Camera, GraphicsError, Quad, Renderable, Shaders, Viewport};
+use input::{Action, Keymap};
+use policy;
+use telemetry::Telemetry;
+
+/// Where session statistics are written when telemetry is enabled.
+const TELEMETRY_FILE: &'static str = "telemetry.json";
+
+/// A debug toggle that stops the player from moving.
+#[cfg(feature = "dev-tools")]
+const FREEZE_TOGGLE: &'static str = "freeze_player";
 
 #[derive(Debug, Clone, Copy)]
 enum Command {
     Quit,
     Move(Direction),
+    Focus(bool),
+    Redraw,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -21,9 +35,39 @@ pub enum Direction {
     Right,
 }
 
+/// How far the player moves per step, in pixels.
+const STEP: i32 = 32;
+const PLAYER_SIZE: (i32, i32) = (32, 32);
+
+/// Gameplay state, kept apart from rendering so that it can be updated
+/// and inspected without a GL context.
+#[derive(Debug, Clone, PartialEq)]
+pub struct World {
+    pub player: (i32, i32),
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World { player: (32, 32) }
+    }
+}
+
+impl World {
+    fn move_player(&mut self, direction: Direction) {
+        match direction {
+            Direction::Up => self.player.1 -= STEP,
+            Direction::Down => self.player.1 += STEP,
+            Direction::Left => self.player.0 -= STEP,
+            Direction::Right => self.player.0 += STEP,
+        }
+    }
+}
+
 pub struct App {
     config: Config,
     display: Display,
+    headless: bool,
+    session: Option<(Session, Scene)>,
 }
 
 impl App {
@@ -31,108 +75,544 @@ impl App {
         use glium::DisplayBuild;
         use glium::glutin::WindowBuilder;
 
-        let display = WindowBuilder::new()
+        let limits = SizeLimits::from_config(&config);
+        let mut builder = WindowBuilder::new()
             .with_dimensions(config.window_width, config.window_height)
             .with_title(env!("CARGO_PKG_NAME"))
+            .with_transparency(config.transparent)
+            .with_decorations(config.decorations)
+            .with_stencil_buffer(8);
+
+        if let Some((width, height)) = limits.min { builder = builder.with_min_dimensions(width, height) }
+        if let Some((width, height)) = limits.max { builder = builder.with_max_dimensions(width, height) }
+
+        let display = if config.fullscreen {
+            let monitor = select_monitor(config.monitor);
+            builder.clone().with_fullscreen(monitor).build_glium().or_else(|err| {
+                policy::degrade(config.strict_mode, "Fullscreen unavailable, falling back to a window", err);
+                builder.build_glium()
+            })
+        } else {
+            builder.build_glium()
+        };
+        let display = display.expect("Attempting to build Glium window");
+
+        App {
+            config: config,
+            display: display,
+            headless: false,
+            session: None,
+        }
+    }
+
+    /// Creates an app that renders into an offscreen context instead of
+    /// a window. Nothing here is process-wide, so several can run side
+    /// by side, for example in tests. There are no window events to end
+    /// it, so drive it with `run_frames` rather than `run`.
+    #[allow(dead_code)] // Used from tests, not from `main`.
+    pub fn headless(config: Config) -> Self {
+        use glium::DisplayBuild;
+        use glium::glutin::HeadlessRendererBuilder;
+
+        let display = HeadlessRendererBuilder::new(config.window_width, config.window_height)
             .build_glium()
-            .expect("Attempting to build Glium window");
+            .expect("Attempting to build headless Glium context");
 
         App {
             config: config,
             display: display,
+            headless: true,
+            session: None,
         }
     }
 
-    pub fn run(self) {
-        use graphics::Quad;
+    pub fn run(self) -> Result<(), GraphicsError> {
+        let App { config, display, headless, session } = self;
+        let (mut session, mut scene) = match session {
+            Some(session) => session,
+            None => (Session::new(&config), try!(Scene::new(&config, &display, headless))),
+        };
+
+        let frame_rate = FrameRate::new(config.frame_rate, config.background_frame_rate);
+
+        let mut telemetry = if config.telemetry {
+            let gpu = display.get_opengl_renderer_string().to_owned();
+            Some(Telemetry::new(TELEMETRY_FILE, gpu, config.strict_mode))
+        } else {
+            None
+        };
+
+        GameLoop::new(frame_rate).run(|frame_time, frame_rate| {
+            if let Some(ref mut telemetry) = telemetry { telemetry.record_frame(frame_time) }
+            step(&mut session, &mut scene, display.poll_events(), &display, frame_rate)
+        });
 
-        let mut commands: Vec<Command> = Vec::new();
-        let mut events = self.display.poll_events();
+        if let Some(ref mut telemetry) = telemetry { telemetry.finish() }
 
-        let mut quad: Quad = Quad::new(&self.display, (32, 32), (32, 32));
+        Ok(())
+    }
 
-        GameLoop::new(self.config.frame_rate).run(|_| {
-            process_events(&mut events, &mut commands);
-            if !update_and_keep_running(&mut commands, &mut quad) { return false }
-            render(&self.display, &quad);
+    /// Runs up to `frames` frames as fast as possible, drawing each one,
+    /// and stops early if the game quits. `script` is called with each
+    /// frame's number and returns keys to release during that frame, on
+    /// top of any real input. Returns whether the game is still running.
+    /// The world carries over between calls.
+    #[allow(dead_code)] // Used from tests, not from `main`.
+    pub fn run_frames<F>(&mut self, frames: u64, mut script: F) -> Result<bool, GraphicsError>
+        where F: FnMut(u64) -> Vec<VirtualKeyCode>
+    {
+        if self.session.is_none() {
+            let scene = try!(Scene::new(&self.config, &self.display, self.headless));
+            self.session = Some((Session::new(&self.config), scene));
+        }
 
-            true
-        });
+        let (ref mut session, ref mut scene) = *self.session.as_mut().unwrap();
+        let mut frame_rate = FrameRate::new(self.config.frame_rate, self.config.background_frame_rate);
+
+        for _ in 0..frames {
+            let scripted = key_releases(script(session.frame));
+            let events = self.display.poll_events().chain(scripted);
+
+            if !step(session, scene, events, &self.display, &mut frame_rate) { return Ok(false) }
+        }
+
+        Ok(true)
     }
+
+    /// Asks for the next frame to be drawn, for when something changes
+    /// that input alone would not redraw in render-on-demand mode.
+    #[allow(dead_code)] // `main` runs the game without invalidating it.
+    pub fn invalidate(&mut self) {
+        if let Some((ref mut session, _)) = self.session { session.invalidate() }
+    }
+
+    /// The game's state, once it has run at least one frame.
+    #[allow(dead_code)] // Used from tests, not from `main`.
+    pub fn world(&self) -> Option<&World> {
+        self.session.as_ref().map(|&(ref session, _)| &session.world)
+    }
+}
+
+/// Runs one frame: handles `events`, updates the world and draws it if
+/// needed. Returns false once the game quits.
+fn step<I>(session: &mut Session, scene: &mut Scene, events: I, display: &Display, frame_rate: &mut FrameRate)
+           -> bool
+    where I: Iterator<Item = Event>
+{
+    if !session.update(events, Some(display), frame_rate) { return false }
+    if session.needs_redraw(frame_rate) { scene.draw(&session.world, display) }
+
+    true
 }
 
-fn process_events<I: Iterator<Item = Event>>(events: &mut I, commands: &mut Vec<Command>) {
+/// Key release events for scripted input.
+fn key_releases(keys: Vec<VirtualKeyCode>) -> Vec<Event> {
     use glium::glutin::ElementState;
 
-    if let Some(event) = events.next() {
+    keys.into_iter().map(|key| Event::KeyboardInput(ElementState::Released, 0, Some(key))).collect()
+}
+
+/// The game's state from frame to frame, which needs no GL context.
+struct Session {
+    world: World,
+    limits: SizeLimits,
+    keymap: Keymap,
+    dev_tools: DevTools,
+    commands: Vec<Command>,
+    render_on_demand: bool,
+    invalidated: bool,
+    frame: u64,
+}
+
+impl Session {
+    fn new(config: &Config) -> Session {
+        Session {
+            world: World::default(),
+            limits: SizeLimits::from_config(config),
+            keymap: Keymap::from_bindings(&config.key_bindings, config.strict_mode),
+            dev_tools: dev_tools(config),
+            commands: Vec::new(),
+            render_on_demand: config.render_on_demand,
+            invalidated: true,
+            frame: 0,
+        }
+    }
+
+    /// Handles `events` and updates the world. Without a `window`,
+    /// resizes are not constrained. Returns false once the game quits.
+    fn update<I>(&mut self, mut events: I, window: Option<&Display>, frame_rate: &mut FrameRate) -> bool
+        where I: Iterator<Item = Event>
+    {
+        self.frame += 1;
+
+        process_events(&mut events, &mut self.commands, &self.keymap, &mut self.dev_tools, window, &self.limits);
+        filter_commands(&self.dev_tools, &mut self.commands);
+        self.invalidated |= !self.commands.is_empty();
+
+        update_and_keep_running(&mut self.commands, &mut self.world, frame_rate)
+    }
+
+    fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    /// Whether this frame should be drawn, clearing any invalidation.
+    fn needs_redraw(&mut self, frame_rate: &FrameRate) -> bool {
+        let redraw = self.invalidated || !(self.render_on_demand || frame_rate.on_demand());
+        self.invalidated = false;
+        redraw
+    }
+}
+
+/// What the game draws with, tied to a GL context.
+struct Scene {
+    quad: Quad,
+    viewport: Viewport,
+    camera: Camera,
+    clear_color: (f32, f32, f32, f32),
+}
+
+impl Scene {
+    fn new(config: &Config, display: &Display, headless: bool) -> Result<Scene, GraphicsError> {
+        let shaders = try!(Shaders::load(display, &config.shader_dir));
+        let mut quad = Quad::new(display, &shaders, World::default().player, PLAYER_SIZE, config.player_color);
+        quad.set_blend_mode(config.blend_mode);
+
+        // Headless contexts report a fixed framebuffer size whatever size
+        // was asked for, so lay the game out for the configured size.
+        let resolution = (config.window_width, config.window_height);
+        let mut viewport = Viewport::new(resolution, config.scale_mode);
+        if headless { viewport.set_surface_size(Some(resolution)) }
+
+        Ok(Scene {
+            quad: quad,
+            viewport: viewport,
+            camera: Camera::new(resolution),
+            clear_color: if config.transparent { (0.0, 0.0, 0.0, 0.0) } else { (0.1, 0.1, 0.1, 1.0) },
+        })
+    }
+
+    fn draw(&mut self, world: &World, display: &Display) {
+        if self.quad.position() != world.player { self.quad.set_position(world.player) }
+
+        render(display, self.clear_color, &self.viewport, &self.camera, &mut [&self.quad]);
+    }
+}
+
+/// Picks the monitor at `index` in the system's monitor list, falling
+/// back to the primary monitor if there is no such monitor.
+fn select_monitor(index: Option<usize>) -> MonitorId {
+    use glium::glutin::{get_available_monitors, get_primary_monitor};
+
+    index.and_then(|index| get_available_monitors().nth(index)).unwrap_or_else(get_primary_monitor)
+}
+
+/// Debug toggles and cheats, which only exist in builds with the
+/// `dev-tools` feature, and then only when `dev_tools` is configured.
+#[cfg(feature = "dev-tools")]
+type DevTools = Toggles;
+#[cfg(not(feature = "dev-tools"))]
+type DevTools = ();
+
+#[cfg(feature = "dev-tools")]
+fn dev_tools(config: &Config) -> DevTools {
+    let mut toggles = Toggles::new();
+    if config.dev_tools {
+        toggles.register_flag(FREEZE_TOGGLE, false, Some(VirtualKeyCode::F5));
+    }
+
+    toggles
+}
+
+#[cfg(not(feature = "dev-tools"))]
+fn dev_tools(_config: &Config) -> DevTools { }
+
+/// Drops the commands that the debug toggles currently suppress.
+#[cfg(feature = "dev-tools")]
+fn filter_commands(toggles: &DevTools, commands: &mut Vec<Command>) {
+    if toggles.flag(FREEZE_TOGGLE) {
+        commands.retain(|command| match *command { Command::Move(_) => false, _ => true });
+    }
+}
+
+#[cfg(not(feature = "dev-tools"))]
+fn filter_commands(_toggles: &DevTools, _commands: &mut Vec<Command>) { }
+
+/// Flips the debug toggle bound to `key`, if any.
+#[cfg(feature = "dev-tools")]
+fn handle_debug_key(toggles: &mut DevTools, key: VirtualKeyCode) {
+    if let Some((name, value)) = toggles.handle_key(key) {
+        println!("Debug: {} {}", name, value);
+    }
+}
+
+#[cfg(not(feature = "dev-tools"))]
+fn handle_debug_key(_toggles: &mut DevTools, _key: VirtualKeyCode) { }
+
+fn process_events<I>(events: &mut I, commands: &mut Vec<Command>, keymap: &Keymap, dev_tools: &mut DevTools,
+                     window: Option<&Display>, limits: &SizeLimits)
+    where I: Iterator<Item = Event>
+{
+    use glium::glutin::ElementState;
+
+    for event in events {
         match event {
             Event::KeyboardInput(ElementState::Released, _, Some(key)) => {
-                if let Some(command) = get_keyboard_command(key) { commands.push(command) }
+                if let Some(command) = get_keyboard_command(keymap, key) {
+                    commands.push(command);
+                } else {
+                    handle_debug_key(dev_tools, key);
+                }
+            },
+            Event::Resized(width, height) => {
+                let (new_width, new_height) = limits.constrain((width, height));
+                if (new_width, new_height) != (width, height) {
+                    if let Some(window) = window.and_then(|window| window.get_window()) {
+                        window.set_inner_size(new_width, new_height)
+                    }
+                }
+                commands.push(Command::Redraw);
             },
+            Event::Refresh => commands.push(Command::Redraw),
+            Event::Focused(focused) => commands.push(Command::Focus(focused)),
+            Event::Suspended(suspended) => commands.push(Command::Focus(!suspended)),
             _ => { }
         }
     }
 }
 
-fn update_and_keep_running(commands: &mut Vec<Command>, quad: &mut Quad) -> bool {
-    match commands.pop() {
-        Some(Command::Quit) => return false,
-        Some(Command::Move(direction)) => quad.translate(direction),
-        _ => { }
+fn update_and_keep_running(commands: &mut Vec<Command>, world: &mut World, frame_rate: &mut FrameRate) -> bool {
+    for command in commands.drain(..) {
+        match command {
+            Command::Quit => return false,
+            Command::Move(direction) => world.move_player(direction),
+            Command::Focus(focused) => frame_rate.set_focused(focused),
+            Command::Redraw => { },
+        }
     }
 
     true
 }
 
-fn render(window: &Display, quad: &Quad) {
+/// Draws a frame, with `renderables` sorted by layer so that
+/// backgrounds, entities and UI overlap predictably.
+fn render<'entity>(window: &Display, clear_color: (f32, f32, f32, f32), viewport: &Viewport, camera: &Camera,
+                   renderables: &mut [&'entity Renderable<'entity>]) {
     use glium::Surface;
 
-    use graphics::Render;
+    use graphics::{self, Render};
 
     let mut target = window.draw();
-    target.clear_color(0.1, 0.1, 0.1, 1.0);
+    target.clear_color_and_stencil(clear_color, 0);
 
-    target.render(quad);
+    graphics::sort_by_layer(renderables);
+    for renderable in renderables.iter() {
+        target.render(*renderable, viewport, camera);
+    }
 
     target.finish().unwrap();
 }
 
-fn get_keyboard_command(key: VirtualKeyCode) -> Option<Command> {
-    use glium::glutin::VirtualKeyCode::*;
+fn get_keyboard_command(keymap: &Keymap, key: VirtualKeyCode) -> Option<Command> {
+    keymap.action(key).map(|action| match action {
+        Action::Quit => Command::Quit,
+        Action::MoveUp => Command::Move(Direction::Up),
+        Action::MoveDown => Command::Move(Direction::Down),
+        Action::MoveLeft => Command::Move(Direction::Left),
+        Action::MoveRight => Command::Move(Direction::Right),
+    })
+}
 
-    match key {
-        Escape => Some(Command::Quit),
-        Up => Some(Command::Move(Direction::Up)),
-        Down => Some(Command::Move(Direction::Down)),
-        Left => Some(Command::Move(Direction::Left)),
-        Right => Some(Command::Move(Direction::Right)),
-        _ => None
+/// Runs the game's update step without a window or GL context, feeding
+/// it scripted key presses, so that gameplay can be checked in tests.
+#[cfg(test)]
+pub struct TestApp {
+    session: Session,
+    frame_rate: FrameRate,
+    running: bool,
+}
+
+#[cfg(test)]
+impl TestApp {
+    pub fn new(config: &Config) -> Self {
+        let mut config = config.clone();
+        config.strict_mode = true;
+
+        TestApp {
+            session: Session::new(&config),
+            frame_rate: FrameRate::new(config.frame_rate, config.background_frame_rate),
+            running: true,
+        }
     }
+
+    /// Runs up to `frames` frames, stopping early if the game quits.
+    /// `script` is called with each frame's number and returns the keys
+    /// released during that frame.
+    pub fn run<F>(&mut self, frames: u64, mut script: F) -> &mut Self
+        where F: FnMut(u64) -> Vec<VirtualKeyCode>
+    {
+        for _ in 0..frames {
+            if !self.running { break }
+
+            let events = key_releases(script(self.session.frame)).into_iter();
+            self.running = self.session.update(events, None, &mut self.frame_rate);
+        }
+
+        self
+    }
+
+    pub fn world(&self) -> &World {
+        &self.session.world
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.session.frame
+    }
+}
+
+/// The sizes the window may take, derived from `Config`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SizeLimits {
+    min: Option<(u32, u32)>,
+    max: Option<(u32, u32)>,
+    /// When set, the window is snapped down to a whole multiple of this
+    /// that lies within `min` and `max`.
+    step: Option<(u32, u32)>,
+}
+
+impl SizeLimits {
+    fn from_config(config: &Config) -> Self {
+        use graphics::ScaleMode;
+
+        let resolution = (config.window_width, config.window_height);
+
+        if !config.resizable {
+            return SizeLimits { min: Some(resolution), max: Some(resolution), step: None };
+        }
+
+        SizeLimits {
+            min: config.min_size,
+            max: config.max_size,
+            step: if config.scale_mode == ScaleMode::Integer { Some(resolution) } else { None },
+        }
+    }
+
+    /// Clamps `size` to the limits. If no multiple of the step fits
+    /// within them, the step is given up rather than the bounds.
+    fn constrain(&self, size: (u32, u32)) -> (u32, u32) {
+        let (mut width, mut height) = size;
+
+        if let Some((min_width, min_height)) = self.min {
+            width = width.max(min_width);
+            height = height.max(min_height);
+        }
+
+        if let Some((max_width, max_height)) = self.max {
+            width = width.min(max_width);
+            height = height.min(max_height);
+        }
+
+        if let Some((step_width, step_height)) = self.step {
+            let round_up = |value: u32, step: u32| (value + step - 1) / step;
+
+            let fewest = match self.min {
+                Some((min_width, min_height)) =>
+                    round_up(min_width, step_width).max(round_up(min_height, step_height)).max(1),
+                None => 1,
+            };
+            let most = match self.max {
+                Some((max_width, max_height)) => (max_width / step_width).min(max_height / step_height),
+                None => u32::max_value(),
+            };
+
+            if fewest <= most {
+                let multiple = (width / step_width).min(height / step_height).max(fewest).min(most);
+                width = step_width * multiple;
+                height = step_height * multiple;
+            }
+        }
+
+        (width, height)
+    }
+}
+
+/// How often events are checked for while the window is in the
+/// background and only drawing on demand.
+const IDLE_INTERVAL_MS: u64 = 100;
+
+/// The target frame rate, which drops to a lower rate while the window
+/// is in the background to save power.
+struct FrameRate {
+    foreground_interval: Duration,
+    /// `None` when a background rate of zero or less asks for frames only
+    /// to be drawn when something changes.
+    background_interval: Option<Duration>,
+    focused: bool,
+}
+
+impl FrameRate {
+    /// A foreground rate of zero or less leaves the frame rate uncapped.
+    pub fn new(foreground_fps: f32, background_fps: f32) -> Self {
+        FrameRate {
+            foreground_interval: fps_to_interval(foreground_fps).unwrap_or(Duration::from_millis(0)),
+            background_interval: fps_to_interval(background_fps),
+            focused: true,
+        }
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// Whether frames should only be drawn when something has changed.
+    fn on_demand(&self) -> bool {
+        !self.focused && self.background_interval.is_none()
+    }
+
+    fn interval(&self) -> Duration {
+        if self.focused {
+            self.foreground_interval
+        } else {
+            self.background_interval.unwrap_or(Duration::from_millis(IDLE_INTERVAL_MS))
+        }
+    }
+}
+
+/// The time between frames at `fps`, or `None` if it is not a positive
+/// rate.
+fn fps_to_interval(fps: f32) -> Option<Duration> {
+    if !(fps > 0.0) { return None }
+
+    Some(Duration::from_millis((1_000.0 / fps) as u64))
 }
 
 struct GameLoop {
-    frame_interval: Duration,
-    frame_count: u8,
+    frame_rate: FrameRate,
+    frame_count: u32,
     previous_instant: Instant,
     previous_second: Instant,
 }
 
 impl GameLoop {
-    pub fn new(target_fps: f32) -> Self {
+    pub fn new(frame_rate: FrameRate) -> Self {
         GameLoop {
-            frame_interval: Duration::from_millis(1_000 / target_fps as u64),
+            frame_rate: frame_rate,
             frame_count: 0,
             previous_instant: Instant::now(),
             previous_second: Instant::now(),
         }
     }
 
-    pub fn run<F: FnMut(Duration) -> bool>(mut self, mut loop_operation: F) {
+    pub fn run<F: FnMut(Duration, &mut FrameRate) -> bool>(mut self, mut loop_operation: F) {
         loop {
             let current_instant = Instant::now();
 
             if let FrameThrottler::Run(duration) = self.throttle(current_instant) {
-                if !loop_operation(duration) { break }
+                if !loop_operation(duration, &mut self.frame_rate) { break }
 
                 self.previous_instant = current_instant;
                 self.update_fps_display(current_instant);
@@ -146,9 +626,10 @@ impl GameLoop {
         use std::thread;
 
         let delta = current_instant - self.previous_instant;
+        let frame_interval = self.frame_rate.interval();
 
-        if delta < self.frame_interval {
-            thread::sleep(self.frame_interval - delta);
+        if delta < frame_interval {
+            thread::sleep(frame_interval - delta);
             return FrameThrottler::Skip;
         }
 
@@ -174,3 +655,135 @@ enum FrameThrottler {
     Skip,
     Run(Duration),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_player_moves_while_right_is_pressed() {
+        use glium::glutin::VirtualKeyCode;
+
+        let mut app = TestApp::new(&Config::default());
+        app.run(60, |_| vec![VirtualKeyCode::Right]);
+
+        assert!(app.world().player.0 > 100);
+        assert_eq!(32, app.world().player.1);
+    }
+
+    #[test]
+    fn test_escape_quits() {
+        use glium::glutin::VirtualKeyCode;
+
+        let mut app = TestApp::new(&Config::default());
+        app.run(10, |frame| if frame == 3 { vec![VirtualKeyCode::Escape] } else { vec![] });
+
+        assert!(!app.is_running());
+        assert_eq!(4, app.frame());
+    }
+
+    #[test]
+    #[ignore] // Needs a headless OpenGL driver, such as OSMesa, and the shaders.
+    fn test_headless_apps_run_side_by_side() {
+        use glium::glutin::VirtualKeyCode;
+
+        let mut moving = App::headless(Config::default());
+        let mut idle = App::headless(Config::default());
+
+        assert!(moving.run_frames(3, |_| vec![VirtualKeyCode::Right]).unwrap());
+        assert!(idle.run_frames(3, |_| vec![]).unwrap());
+        assert!(moving.run_frames(2, |_| vec![]).unwrap());
+        assert!(!idle.run_frames(5, |frame| if frame == 4 { vec![VirtualKeyCode::Escape] } else { vec![] }).unwrap());
+
+        assert_eq!((32 + 3 * STEP, 32), moving.world().unwrap().player);
+        assert_eq!((32, 32), idle.world().unwrap().player);
+    }
+
+    #[test]
+    fn test_invalidate_redraws_on_demand() {
+        let mut config = Config::default();
+        config.render_on_demand = true;
+        let frame_rate = FrameRate::new(config.frame_rate, config.background_frame_rate);
+        let mut session = Session::new(&config);
+
+        assert!(session.needs_redraw(&frame_rate));
+        assert!(!session.needs_redraw(&frame_rate));
+
+        session.invalidate();
+        assert!(session.needs_redraw(&frame_rate));
+    }
+
+    #[test]
+    fn test_size_limits_clamp() {
+        let limits = SizeLimits { min: Some((320, 240)), max: Some((1280, 960)), step: None };
+
+        assert_eq!((320, 240), limits.constrain((100, 100)));
+        assert_eq!((1280, 960), limits.constrain((2000, 2000)));
+        assert_eq!((800, 600), limits.constrain((800, 600)));
+    }
+
+    #[test]
+    fn test_size_limits_integer_steps() {
+        let limits = SizeLimits { min: None, max: None, step: Some((320, 240)) };
+
+        assert_eq!((640, 480), limits.constrain((700, 500)));
+        assert_eq!((320, 240), limits.constrain((100, 100)));
+    }
+
+    #[test]
+    fn test_size_limits_steps_within_bounds() {
+        let limits = SizeLimits { min: Some((400, 300)), max: Some((1000, 800)), step: Some((320, 240)) };
+        assert_eq!((640, 480), limits.constrain((500, 400)));
+        assert_eq!((960, 720), limits.constrain((2000, 2000)));
+
+        let limits = SizeLimits { min: None, max: Some((300, 200)), step: Some((320, 240)) };
+        assert_eq!((300, 200), limits.constrain((500, 400)));
+    }
+
+    #[test]
+    fn test_size_limits_fixed_window() {
+        let mut config = Config::default();
+        config.resizable = false;
+        let limits = SizeLimits::from_config(&config);
+
+        assert_eq!((640, 480), limits.constrain((1024, 768)));
+    }
+
+    #[test]
+    fn test_frame_rate_drops_in_background() {
+        let mut frame_rate = FrameRate::new(60.0, 10.0);
+        assert_eq!(Duration::from_millis(16), frame_rate.interval());
+
+        frame_rate.set_focused(false);
+        assert_eq!(Duration::from_millis(100), frame_rate.interval());
+
+        frame_rate.set_focused(true);
+        assert_eq!(Duration::from_millis(16), frame_rate.interval());
+    }
+
+    #[test]
+    fn test_frame_rate_without_positive_rates() {
+        use std::f32;
+
+        for &background_fps in &[0.0, -1.0, f32::NAN] {
+            let mut frame_rate = FrameRate::new(60.0, background_fps);
+            assert!(!frame_rate.on_demand());
+
+            frame_rate.set_focused(false);
+            assert!(frame_rate.on_demand());
+            assert_eq!(Duration::from_millis(IDLE_INTERVAL_MS), frame_rate.interval());
+        }
+
+        assert_eq!(Duration::from_millis(0), FrameRate::new(0.0, 10.0).interval());
+        assert_eq!(Duration::from_millis(2_000), FrameRate::new(0.5, 10.0).interval());
+    }
+
+    #[test]
+    fn test_fps_display_counts_uncapped_frames() {
+        let mut game_loop = GameLoop::new(FrameRate::new(0.0, 10.0));
+        let within_a_second = game_loop.previous_second;
+
+        for _ in 0..1_000 { game_loop.update_fps_display(within_a_second) }
+        assert_eq!(1_000, game_loop.frame_count);
+    }
+}
@@ -3,16 +3,54 @@
 
 use clap::App;
 use serde_yaml;
+use std::collections::HashMap;
 use std::error::Error;
 use std::io;
 use std::fmt;
 use std::path::Path;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+use graphics::{BlendMode, Color, ScaleMode};
+use input::Action;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub window_width: u32,
     pub window_height: u32,
     pub frame_rate: f32,
+    #[serde(default = "default_background_frame_rate")]
+    pub background_frame_rate: f32,
+    #[serde(default)]
+    pub render_on_demand: bool,
+    #[serde(default)]
+    pub scale_mode: ScaleMode,
+    #[serde(default = "default_resizable")]
+    pub resizable: bool,
+    #[serde(default)]
+    pub min_size: Option<(u32, u32)>,
+    #[serde(default)]
+    pub max_size: Option<(u32, u32)>,
+    #[serde(default)]
+    pub fullscreen: bool,
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default = "default_decorations")]
+    pub decorations: bool,
+    #[serde(default)]
+    pub key_bindings: HashMap<String, Action>,
+    #[serde(default = "default_shader_dir")]
+    pub shader_dir: String,
+    #[serde(default = "default_player_color")]
+    pub player_color: Color,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    #[serde(default)]
+    pub telemetry: bool,
+    #[serde(default)]
+    pub strict_mode: bool,
+    #[serde(default)]
+    pub dev_tools: bool,
 }
 
 impl Default for Config {
@@ -21,10 +59,47 @@ impl Default for Config {
             window_width: 640,
             window_height: 480,
             frame_rate: 60.0,
+            background_frame_rate: default_background_frame_rate(),
+            render_on_demand: false,
+            scale_mode: ScaleMode::default(),
+            resizable: default_resizable(),
+            min_size: None,
+            max_size: None,
+            fullscreen: false,
+            monitor: None,
+            transparent: false,
+            decorations: default_decorations(),
+            key_bindings: HashMap::new(),
+            shader_dir: default_shader_dir(),
+            player_color: default_player_color(),
+            blend_mode: BlendMode::default(),
+            telemetry: false,
+            strict_mode: false,
+            dev_tools: false,
         }
     }
 }
 
+fn default_background_frame_rate() -> f32 {
+    10.0
+}
+
+fn default_resizable() -> bool {
+    true
+}
+
+fn default_decorations() -> bool {
+    true
+}
+
+fn default_shader_dir() -> String {
+    "assets/shaders".to_owned()
+}
+
+fn default_player_color() -> Color {
+    Color::rgba(1.0, 0.0, 0.0, 1.0)
+}
+
 pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
     use std::fs::File;
 
@@ -40,12 +115,19 @@ pub fn apply_session_overrides(mut config: Config) -> Config {
 
     if let Some(new_width) = overridden_value("width") { config.window_width = new_width }
     if let Some(new_height) = overridden_value("height") { config.window_height = new_height }
+    if overrides.is_present("fullscreen") { config.fullscreen = true }
+    if overrides.is_present("strict") { config.strict_mode = true }
 
     config
 }
 
+/// The name of the subcommand given on the command line, if any.
+pub fn requested_subcommand() -> Option<String> {
+    get_defined_cli().get_matches().subcommand_name().map(String::from)
+}
+
 fn get_defined_cli<'a, 'b>() -> App<'a, 'b> {
-    use clap::Arg;
+    use clap::{Arg, SubCommand};
 
     App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
@@ -63,6 +145,15 @@ fn get_defined_cli<'a, 'b>() -> App<'a, 'b> {
              .value_name("VALUE")
              .help("Sets the height of the window")
              .takes_value(true))
+        .arg(Arg::with_name("fullscreen")
+             .short("F")
+             .long("fullscreen")
+             .help("Runs in exclusive fullscreen on the configured monitor"))
+        .arg(Arg::with_name("strict")
+             .long("strict")
+             .help("Treats failures in optional features as fatal"))
+        .subcommand(SubCommand::with_name("doctor")
+                    .about("Checks that this system can run the game and prints a summary"))
 }
 
 #[derive(Debug)]
@@ -0,0 +1,137 @@
+//! A registry of named runtime toggles, such as god mode or a time
+//! scale, that any system can register and read, and that can be flipped
+//! with hotkeys while debugging. Only built with the `dev-tools` feature.
+
+use glium::glutin::VirtualKeyCode;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToggleValue {
+    Flag(bool),
+    Scalar(f32),
+}
+
+impl fmt::Display for ToggleValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ToggleValue::Flag(value) => write!(f, "{}", if value { "on" } else { "off" }),
+            ToggleValue::Scalar(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+struct Toggle {
+    value: ToggleValue,
+    key: Option<VirtualKeyCode>,
+}
+
+/// Toggles are kept sorted by name so that listings are stable.
+#[derive(Default)]
+pub struct Toggles {
+    toggles: BTreeMap<String, Toggle>,
+}
+
+impl Toggles {
+    pub fn new() -> Self {
+        Toggles { toggles: BTreeMap::new() }
+    }
+
+    /// Registers an on/off toggle, optionally flipped by `key`.
+    /// Registering a name again replaces the earlier toggle.
+    pub fn register_flag(&mut self, name: &str, default: bool, key: Option<VirtualKeyCode>) {
+        self.toggles.insert(name.to_owned(), Toggle { value: ToggleValue::Flag(default), key: key });
+    }
+
+    #[allow(dead_code)] // No scalar toggles are registered yet.
+    pub fn register_scalar(&mut self, name: &str, default: f32) {
+        self.toggles.insert(name.to_owned(), Toggle { value: ToggleValue::Scalar(default), key: None });
+    }
+
+    /// Whether the named flag is on. Unregistered flags are off.
+    pub fn flag(&self, name: &str) -> bool {
+        match self.get(name) {
+            Some(ToggleValue::Flag(value)) => value,
+            _ => false,
+        }
+    }
+
+    #[allow(dead_code)] // No scalar toggles are registered yet.
+    pub fn scalar(&self, name: &str) -> Option<f32> {
+        match self.get(name) {
+            Some(ToggleValue::Scalar(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Sets a registered toggle, returning false if there is no toggle of
+    /// that name and kind.
+    #[allow(dead_code)] // For a console, which doesn't exist yet.
+    pub fn set(&mut self, name: &str, value: ToggleValue) -> bool {
+        match self.toggles.get_mut(name) {
+            Some(toggle) => match (toggle.value, value) {
+                (ToggleValue::Flag(_), ToggleValue::Flag(_)) |
+                (ToggleValue::Scalar(_), ToggleValue::Scalar(_)) => { toggle.value = value; true },
+                _ => false,
+            },
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<ToggleValue> {
+        self.toggles.get(name).map(|toggle| toggle.value)
+    }
+
+    /// Flips the flag bound to `key`, if any, returning its name and new
+    /// value.
+    pub fn handle_key(&mut self, key: VirtualKeyCode) -> Option<(&str, ToggleValue)> {
+        for (name, toggle) in &mut self.toggles {
+            if toggle.key != Some(key) { continue }
+
+            if let ToggleValue::Flag(value) = toggle.value {
+                toggle.value = ToggleValue::Flag(!value);
+                return Some((name, toggle.value));
+            }
+        }
+
+        None
+    }
+
+    /// Every toggle and its current value, sorted by name, for listing
+    /// in debug output.
+    #[allow(dead_code)] // For a console, which doesn't exist yet.
+    pub fn list(&self) -> Vec<(&str, ToggleValue)> {
+        self.toggles.iter().map(|(name, toggle)| (name.as_str(), toggle.value)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glium::glutin::VirtualKeyCode;
+
+    #[test]
+    fn test_hotkeys_flip_flags() {
+        let mut toggles = Toggles::new();
+        toggles.register_flag("god_mode", false, Some(VirtualKeyCode::F1));
+
+        assert_eq!(Some(("god_mode", ToggleValue::Flag(true))), toggles.handle_key(VirtualKeyCode::F1));
+        assert!(toggles.flag("god_mode"));
+        assert_eq!(None, toggles.handle_key(VirtualKeyCode::F2));
+        assert!(!toggles.flag("missing"));
+    }
+
+    #[test]
+    fn test_set_checks_kind() {
+        let mut toggles = Toggles::new();
+        toggles.register_scalar("time_scale", 1.0);
+        toggles.register_flag("show_colliders", false, None);
+
+        assert!(toggles.set("time_scale", ToggleValue::Scalar(0.5)));
+        assert!(!toggles.set("time_scale", ToggleValue::Flag(true)));
+        assert!(!toggles.set("missing", ToggleValue::Scalar(2.0)));
+
+        assert_eq!(vec![("show_colliders", ToggleValue::Flag(false)), ("time_scale", ToggleValue::Scalar(0.5))],
+                   toggles.list());
+    }
+}
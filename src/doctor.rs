@@ -0,0 +1,104 @@
+//! Implements `scintillis doctor`, which checks that the environment can
+//! run the game and prints a summary suitable for pasting into a bug
+//! report.
+
+use glium::Display;
+use std::fs;
+use std::path::Path;
+
+use config::{self, Config, ConfigError};
+use graphics::Shaders;
+
+/// The outcome of a single check, with a line of detail either way.
+type Check = Result<String, String>;
+
+/// Runs every check, printing each result. Returns whether all passed.
+pub fn run(config_path: &Path) -> bool {
+    let config = config::load_from_file(config_path);
+    let shader_dir = match config {
+        Ok(ref config) => config.shader_dir.clone(),
+        Err(_) => Config::default().shader_dir,
+    };
+    let display = open_context();
+
+    let checks = [
+        ("Config", check_config(config_path, &config)),
+        ("OpenGL", check_opengl(&display)),
+        ("Shaders", check_shaders(&display, Path::new(&shader_dir))),
+        ("Data directory", check_data_dir(Path::new("."))),
+    ];
+
+    let mut all_passed = true;
+
+    for &(name, ref check) in checks.iter() {
+        match *check {
+            Ok(ref detail) => println!("[ ok ] {}: {}", name, detail),
+            Err(ref detail) => {
+                println!("[FAIL] {}: {}", name, detail);
+                all_passed = false;
+            },
+        }
+    }
+
+    all_passed
+}
+
+fn check_config(path: &Path, config: &Result<Config, ConfigError>) -> Check {
+    match *config {
+        Ok(_) => Ok(format!("{} is valid", path.display())),
+        Err(ref err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+/// Opens a hidden window like the game's, for the checks that need an
+/// OpenGL context.
+fn open_context() -> Result<Display, String> {
+    use glium::DisplayBuild;
+    use glium::glutin::WindowBuilder;
+
+    WindowBuilder::new()
+        .with_visibility(false)
+        .with_stencil_buffer(8)
+        .build_glium()
+        .map_err(|err| format!("could not create a context: {}", err))
+}
+
+/// Checks that the context supports the GLSL version the game's shaders
+/// are written for.
+fn check_opengl(display: &Result<Display, String>) -> Check {
+    use glium::{Api, Version};
+
+    let display = try!(display.as_ref().map_err(|err| err.clone()));
+
+    let summary = format!("{} ({}, {})",
+                          display.get_opengl_version_string(),
+                          display.get_opengl_vendor_string(),
+                          display.get_opengl_renderer_string());
+
+    if display.get_supported_glsl_version() >= Version(Api::Gl, 1, 40) {
+        Ok(summary)
+    } else {
+        Err(format!("GLSL 1.40 is required: {}", summary))
+    }
+}
+
+/// Compiles the shaders from the configured shader directory, as the game
+/// does on startup.
+fn check_shaders(display: &Result<Display, String>, dir: &Path) -> Check {
+    let display = try!(display.as_ref().map_err(|err| err.clone()));
+
+    match Shaders::load(display, dir) {
+        Ok(_) => Ok(format!("{} compiled", dir.display())),
+        Err(err) => Err(format!("{}: {}", dir.display(), err)),
+    }
+}
+
+/// Checks that files such as telemetry can be written next to the game.
+fn check_data_dir(dir: &Path) -> Check {
+    let probe = dir.join(".scintillis-doctor");
+
+    try!(fs::File::create(&probe).map_err(|err| format!("{} is not writable: {}", dir.display(), err)));
+    try!(fs::remove_file(&probe).map_err(|err| format!("could not clean up {}: {}", probe.display(), err)));
+
+    Ok(format!("{} is writable", dir.display()))
+}
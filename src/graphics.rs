@@ -1,153 +1,1714 @@
 //! Abstractions for the OpenGL graphics pipeline
 
-use glium::{Display, Frame, Program, Surface, VertexBuffer};
-use glium::index::NoIndices;
-
-use app::Direction;
+use glium::{Blend, Display, DrawParameters, IndexBuffer, Program, ProgramCreationError, Rect, StencilOperation,
+            StencilTest, Surface, Texture2d, VertexBuffer};
+use glium::draw_parameters::Stencil;
+use glium::framebuffer::{RenderBufferCreationError, SimpleFrameBuffer, StencilRenderBuffer, ValidationError};
+use glium::texture::TextureCreationError;
+use glium::uniforms::{UniformType, UniformValue, Uniforms};
+use image::{self, ImageError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 type Coord = (i32, i32);
 type Size = (i32, i32);
 
-#[derive(Debug, Clone, Copy)]
-pub struct Vertex {
-    position: [f32; 2],
+#[derive(Debug, Clone, Copy)]
+pub struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+implement_vertex!(Vertex, position, tex_coords, color);
+
+/// A rectangle drawn from a static vertex buffer in its own local space,
+/// which is placed in the world by a model transform on the GPU.
+pub struct Quad {
+    position: Coord,
+    size: Size,
+    rotation: f32,
+    scale: f32,
+    vertices: VertexBuffer<Vertex>,
+    indices: Indices,
+    program: Rc<Program>,
+    material: Material,
+    layer: i32,
+    blend_mode: BlendMode,
+    clip: Option<(Coord, Size)>,
+    tex_rect: TexRect,
+    corner_colors: [Color; 4],
+}
+
+impl Quad {
+    /// Creates a quad filled with a single color.
+    pub fn new(window: &Display, shaders: &Shaders, origin: Coord, size: Size, color: Color) -> Self {
+        let mut quad = Quad::with_program(window, origin, size, shaders.solid.clone());
+        quad.set_color(color);
+
+        quad
+    }
+
+    /// Creates a quad whose color is blended between the given corner
+    /// colors, in the order top-left, top-right, bottom-left,
+    /// bottom-right.
+    #[allow(dead_code)] // No scene uses gradients yet.
+    pub fn with_vertex_colors(window: &Display, shaders: &Shaders, origin: Coord, size: Size,
+                              corner_colors: [Color; 4]) -> Self {
+        let mut quad = Quad::with_program(window, origin, size, shaders.vertex_color.clone());
+        quad.corner_colors = corner_colors;
+        quad.update_vertices();
+
+        quad
+    }
+
+    /// Creates a quad that displays the whole of `texture`, stretched to
+    /// the quad's size.
+    pub fn textured(window: &Display, shaders: &Shaders, origin: Coord, size: Size,
+                    texture: Rc<Texture2d>) -> Self {
+        let mut quad = Quad::with_program(window, origin, size, shaders.textured.clone());
+        quad.material.set(TEXTURE_UNIFORM, MaterialValue::Texture(texture));
+
+        quad
+    }
+
+    /// Creates a quad that displays the named region of `atlas`, or
+    /// `None` if the atlas has no such region.
+    #[allow(dead_code)] // Nothing in the demo scene uses an atlas yet.
+    pub fn sprite(window: &Display, shaders: &Shaders, origin: Coord, size: Size, atlas: &Atlas,
+                  name: &str) -> Option<Self> {
+        atlas.tex_rect(name).map(|tex_rect| {
+            let mut quad = Quad::textured(window, shaders, origin, size, atlas.texture().clone());
+            quad.set_tex_rect(tex_rect);
+
+            quad
+        })
+    }
+
+    /// Switches to displaying another region of the atlas this quad was
+    /// created from. Returns false, leaving the quad unchanged, if there
+    /// is no such region or `atlas` is not the quad's texture.
+    #[allow(dead_code)] // Nothing in the demo scene uses an atlas yet.
+    pub fn set_sprite(&mut self, atlas: &Atlas, name: &str) -> bool {
+        match self.material.get(TEXTURE_UNIFORM) {
+            Some(&MaterialValue::Texture(ref texture)) if Rc::ptr_eq(texture, atlas.texture()) => { },
+            _ => return false,
+        }
+
+        match atlas.tex_rect(name) {
+            Some(tex_rect) => { self.set_tex_rect(tex_rect); true },
+            None => false,
+        }
+    }
+
+    fn set_tex_rect(&mut self, tex_rect: TexRect) {
+        self.tex_rect = tex_rect;
+        self.update_vertices();
+    }
+
+    fn update_vertices(&mut self) {
+        self.vertices.write(&quad_vertices((0, 0), self.size, self.tex_rect, self.corner_colors));
+    }
+
+    fn with_program(window: &Display, origin: Coord, size: Size, program: Rc<Program>) -> Self {
+        let vertices = quad_vertices((0, 0), size, TexRect::full(), [WHITE; 4]);
+
+        Quad {
+            position: origin,
+            size: size,
+            rotation: 0.0,
+            scale: 1.0,
+            vertices: VertexBuffer::new(window, &vertices).unwrap(),
+            indices: Indices::Owned(triangle_list(window, &QUAD_INDICES)),
+            program: program,
+            material: Material::new(),
+            layer: ENTITY_LAYER,
+            blend_mode: BlendMode::default(),
+            clip: None,
+            tex_rect: TexRect::full(),
+            corner_colors: [WHITE; 4],
+        }
+    }
+
+    /// Replaces the uniforms passed to this quad's shader, checking
+    /// them against the uniforms the shader declares.
+    #[allow(dead_code)] // The built-in shaders are configured by their constructors.
+    pub fn set_material(&mut self, material: Material) -> Result<(), MaterialError> {
+        try!(material.validate(&self.program));
+        self.material = material;
+
+        Ok(())
+    }
+
+    /// Changes the fill color of a quad created with `Quad::new`.
+    pub fn set_color(&mut self, color: Color) {
+        self.material.set(COLOR_UNIFORM, MaterialValue::Vec4(color.into()));
+    }
+
+    /// Makes this quad draw with an index buffer shared with other quads,
+    /// such as one from `quad_indices`, instead of its own.
+    #[allow(dead_code)] // The demo scene has a single quad, so nothing to share with.
+    pub fn share_indices(&mut self, indices: Rc<IndexBuffer<u16>>) {
+        self.indices = Indices::Shared(indices);
+    }
+
+    #[allow(dead_code)] // Everything in the demo scene is on the entity layer.
+    pub fn set_layer(&mut self, layer: i32) {
+        self.layer = layer;
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    /// Restricts drawing of this quad to the given area, in the same world
+    /// coordinates as the quad's own position, so the clip follows the
+    /// camera. `None` removes the clip.
+    #[allow(dead_code)] // Nothing in the demo scene is clipped yet.
+    pub fn set_clip(&mut self, clip: Option<(Coord, Size)>) {
+        self.clip = clip;
+    }
+
+    pub fn position(&self) -> Coord {
+        self.position
+    }
+
+    /// Moves the quad. Only the model transform changes; the vertex
+    /// buffer is left alone.
+    pub fn set_position(&mut self, origin: Coord) {
+        self.position = origin;
+    }
+
+    /// Turns the quad about its centre by `radians`, clockwise on screen.
+    #[allow(dead_code)] // The player quad is never rotated or scaled.
+    pub fn rotate(&mut self, radians: f32) {
+        self.rotation += radians;
+    }
+
+    #[allow(dead_code)] // The player quad is never rotated or scaled.
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// Grows or shrinks the quad about its centre by `factor`.
+    #[allow(dead_code)] // The player quad is never rotated or scaled.
+    pub fn scale(&mut self, factor: f32) {
+        self.scale *= factor;
+    }
+
+    #[allow(dead_code)] // The player quad is never rotated or scaled.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale
+    }
+
+    fn model(&self) -> [[f32; 4]; 4] {
+        model_matrix(self.position, self.size, self.rotation, self.scale)
+    }
+
+    /// An axis-aligned box containing the quad at any rotation, from the
+    /// circle through its corners.
+    fn bounds(&self) -> (Coord, Size) {
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        let centre = (self.position.0 as f32 + width / 2.0, self.position.1 as f32 + height / 2.0);
+        let radius = (width * width + height * height).sqrt() / 2.0 * self.scale.abs();
+        let extent = (radius * 2.0).ceil() as i32 + 1;
+
+        (((centre.0 - radius).floor() as i32, (centre.1 - radius).floor() as i32), (extent, extent))
+    }
+}
+
+const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// The column-major matrix placing a quad whose local space spans
+/// `size` from the origin: rotated (clockwise, as y points down) and
+/// scaled about its centre, with its unrotated top-left at `position`.
+fn model_matrix(position: Coord, size: Size, rotation: f32, scale: f32) -> [[f32; 4]; 4] {
+    let (centre_x, centre_y) = (size.0 as f32 / 2.0, size.1 as f32 / 2.0);
+    let (cos, sin) = (rotation.cos() * scale, rotation.sin() * scale);
+
+    [
+        [cos, sin, 0.0, 0.0],
+        [-sin, cos, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [position.0 as f32 + centre_x - cos * centre_x + sin * centre_y,
+         position.1 as f32 + centre_y - sin * centre_x - cos * centre_y,
+         0.0,
+         1.0],
+    ]
+}
+
+/// The corners of a quad (top-left, top-right, bottom-left, bottom-right)
+/// in game coordinates, or local coordinates for a lone `Quad`.
+fn quad_vertices(origin: Coord, size: Size, tex_rect: TexRect, colors: [Color; 4]) -> [Vertex; 4] {
+    let left = origin.0 as f32;
+    let right = (origin.0 + size.0) as f32;
+    let top = origin.1 as f32;
+    let bottom = (origin.1 + size.1) as f32;
+
+    let tex = tex_rect;
+
+    [
+        Vertex { position: [left, top], tex_coords: [tex.left, tex.top], color: colors[0].into() },
+        Vertex { position: [right, top], tex_coords: [tex.right, tex.top], color: colors[1].into() },
+        Vertex { position: [left, bottom], tex_coords: [tex.left, tex.bottom], color: colors[2].into() },
+        Vertex { position: [right, bottom], tex_coords: [tex.right, tex.bottom], color: colors[3].into() },
+    ]
+}
+
+/// The two triangles of a quad, indexing the corners from `quad_vertices`.
+const QUAD_INDICES: [u16; 6] = [0, 1, 2, 1, 3, 2];
+
+/// The indices for `count` quads whose corners are stored one after
+/// another in a single vertex buffer.
+fn batch_indices(count: usize) -> Vec<u16> {
+    (0..count)
+        .flat_map(|quad| QUAD_INDICES.iter().map(move |&index| (quad * 4) as u16 + index))
+        .collect()
+}
+
+fn triangle_list(window: &Display, indices: &[u16]) -> IndexBuffer<u16> {
+    use glium::index::PrimitiveType;
+
+    IndexBuffer::new(window, PrimitiveType::TrianglesList, indices).unwrap()
+}
+
+/// An index buffer for a single quad that any number of quads can share
+/// through `Quad::share_indices`.
+#[allow(dead_code)] // See `Quad::share_indices`.
+pub fn quad_indices(window: &Display) -> Rc<IndexBuffer<u16>> {
+    Rc::new(triangle_list(window, &QUAD_INDICES))
+}
+
+/// The index buffer a renderable draws with, either its own or one
+/// shared with other renderables of the same shape.
+pub enum Indices {
+    Owned(IndexBuffer<u16>),
+    Shared(Rc<IndexBuffer<u16>>),
+}
+
+impl Indices {
+    pub fn buffer(&self) -> &IndexBuffer<u16> {
+        match *self {
+            Indices::Owned(ref buffer) => buffer,
+            Indices::Shared(ref buffer) => buffer,
+        }
+    }
+}
+
+/// Identifies a quad within a `QuadBatch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuadId(usize);
+
+#[derive(Debug, Clone, Copy)]
+struct BatchedQuad {
+    position: Coord,
+    size: Size,
+    tex_rect: TexRect,
+    dirty: bool,
+}
+
+/// Many quads sharing one vertex buffer, program and material, drawn
+/// with a single draw call. Changes are only sent to the GPU when
+/// `upload` is called, and only for the quads that changed.
+pub struct QuadBatch {
+    quads: Vec<BatchedQuad>,
+    capacity: usize,
+    vertices: VertexBuffer<Vertex>,
+    indices: Indices,
+    program: Rc<Program>,
+    material: Material,
+    layer: i32,
+    blend_mode: BlendMode,
+}
+
+#[allow(dead_code)] // No scene draws enough quads to batch yet.
+impl QuadBatch {
+    /// Creates a batch whose quads are all filled with `color`.
+    pub fn new(window: &Display, shaders: &Shaders, capacity: usize, color: Color) -> Self {
+        let mut batch = QuadBatch::with_program(window, capacity, shaders.solid.clone());
+        batch.material.set(COLOR_UNIFORM, MaterialValue::Vec4(color.into()));
+
+        batch
+    }
+
+    /// Creates a batch whose quads all sample from `texture`, typically
+    /// an atlas texture.
+    pub fn textured(window: &Display, shaders: &Shaders, capacity: usize, texture: Rc<Texture2d>) -> Self {
+        let mut batch = QuadBatch::with_program(window, capacity, shaders.textured.clone());
+        batch.material.set(TEXTURE_UNIFORM, MaterialValue::Texture(texture));
+
+        batch
+    }
+
+    fn with_program(window: &Display, capacity: usize, program: Rc<Program>) -> Self {
+        assert!(capacity * 4 <= u16::max_value() as usize + 1, "QuadBatch capacity {} is too large", capacity);
+
+        // Unused slots are left as zero-area triangles, which draw nothing.
+        let empty = Vertex { position: [0.0, 0.0], tex_coords: [0.0, 0.0], color: [0.0; 4] };
+
+        QuadBatch {
+            quads: Vec::with_capacity(capacity),
+            capacity: capacity,
+            vertices: VertexBuffer::dynamic(window, &vec![empty; capacity * 4]).unwrap(),
+            indices: Indices::Owned(triangle_list(window, &batch_indices(capacity))),
+            program: program,
+            material: Material::new(),
+            layer: ENTITY_LAYER,
+            blend_mode: BlendMode::default(),
+        }
+    }
+
+    pub fn set_layer(&mut self, layer: i32) {
+        self.layer = layer;
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    /// Adds a quad to the batch, or returns `None` if the batch is full.
+    pub fn push(&mut self, origin: Coord, size: Size, tex_rect: TexRect) -> Option<QuadId> {
+        if self.quads.len() == self.capacity { return None }
+
+        self.quads.push(BatchedQuad { position: origin, size: size, tex_rect: tex_rect, dirty: true });
+        Some(QuadId(self.quads.len() - 1))
+    }
+
+    pub fn set_position(&mut self, id: QuadId, origin: Coord) {
+        let quad = &mut self.quads[id.0];
+        quad.position = origin;
+        quad.dirty = true;
+    }
+
+    pub fn set_tex_rect(&mut self, id: QuadId, tex_rect: TexRect) {
+        let quad = &mut self.quads[id.0];
+        quad.tex_rect = tex_rect;
+        quad.dirty = true;
+    }
+
+    pub fn len(&self) -> usize {
+        self.quads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quads.is_empty()
+    }
+
+    /// Writes the vertices of every quad changed since the last upload
+    /// into the vertex buffer.
+    pub fn upload(&mut self) {
+        for (index, quad) in self.quads.iter_mut().enumerate() {
+            if !quad.dirty { continue }
+
+            let corners = quad_vertices(quad.position, quad.size, quad.tex_rect, [WHITE; 4]);
+            let start = index * 4;
+
+            self.vertices.slice_mut(start .. start + 4).unwrap().write(&corners);
+            quad.dirty = false;
+        }
+    }
+}
+
+/// An area of a texture in texture coordinates, where (0, 0) is the
+/// bottom-left and (1, 1) the top-right of the texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TexRect {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+impl TexRect {
+    pub fn full() -> Self {
+        TexRect { left: 0.0, right: 1.0, bottom: 0.0, top: 1.0 }
+    }
+}
+
+/// An area of an atlas image in pixels, with the origin at the top-left
+/// as in image editors.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Whether the region lies wholly within a texture of the given size.
+    fn fits(&self, texture_size: (u32, u32)) -> bool {
+        let within = |start: u32, length: u32, limit: u32| start.checked_add(length).map_or(false, |end| end <= limit);
+
+        within(self.x, self.width, texture_size.0) && within(self.y, self.height, texture_size.1)
+    }
+
+    fn to_tex_rect(&self, texture_size: (u32, u32)) -> TexRect {
+        let (width, height) = (texture_size.0 as f32, texture_size.1 as f32);
+
+        TexRect {
+            left: self.x as f32 / width,
+            right: (self.x + self.width) as f32 / width,
+            bottom: 1.0 - (self.y + self.height) as f32 / height,
+            top: 1.0 - self.y as f32 / height,
+        }
+    }
+}
+
+/// The file format describing an atlas: an image path, relative to the
+/// description file, and its named regions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AtlasDescription {
+    image: String,
+    regions: HashMap<String, Region>,
+}
+
+/// A single texture holding many images, each addressed by name, so
+/// that sprites can share one texture.
+pub struct Atlas {
+    texture: Rc<Texture2d>,
+    regions: HashMap<String, TexRect>,
+}
+
+impl Atlas {
+    /// Creates an atlas from named regions of `texture`, failing if any
+    /// region reaches outside it.
+    pub fn new(texture: Rc<Texture2d>, regions: HashMap<String, Region>) -> Result<Atlas, AtlasError> {
+        let texture_size = (texture.get_width(), texture.get_height().unwrap_or(1));
+        let mut tex_rects = HashMap::new();
+
+        for (name, region) in regions {
+            if !region.fits(texture_size) { return Err(AtlasError::OutOfBounds(name)) }
+
+            let tex_rect = region.to_tex_rect(texture_size);
+            tex_rects.insert(name, tex_rect);
+        }
+
+        Ok(Atlas {
+            texture: texture,
+            regions: tex_rects,
+        })
+    }
+
+    /// Loads an atlas from a YAML description file such as:
+    ///
+    /// ```yaml
+    /// image: characters.png
+    /// regions:
+    ///   player_idle: { x: 0, y: 0, width: 32, height: 32 }
+    /// ```
+    #[allow(dead_code)] // Nothing in the demo scene uses an atlas yet.
+    pub fn load<P: AsRef<Path>>(window: &Display, path: P) -> Result<Atlas, AtlasError> {
+        use std::fs::File;
+
+        let path = path.as_ref();
+        let description: AtlasDescription = try!(serde_yaml::from_reader(try!(File::open(path))));
+        let image_path = path.parent().unwrap_or(Path::new("")).join(&description.image);
+        let texture = try!(load_texture(window, image_path));
+
+        Atlas::new(Rc::new(texture), description.regions)
+    }
+
+    pub fn texture(&self) -> &Rc<Texture2d> {
+        &self.texture
+    }
+
+    pub fn tex_rect(&self, name: &str) -> Option<TexRect> {
+        self.regions.get(name).cloned()
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(io::Error),
+    Parse(serde_yaml::Error),
+    Texture(TextureError),
+    /// The named region reaches outside the atlas image.
+    OutOfBounds(String),
+}
+
+impl From<io::Error> for AtlasError {
+    fn from(err: io::Error) -> Self {
+        AtlasError::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for AtlasError {
+    fn from(err: serde_yaml::Error) -> Self {
+        AtlasError::Parse(err)
+    }
+}
+
+impl From<TextureError> for AtlasError {
+    fn from(err: TextureError) -> Self {
+        AtlasError::Texture(err)
+    }
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AtlasError::Io(ref err) => err.fmt(f),
+            AtlasError::Parse(ref err) => err.fmt(f),
+            AtlasError::Texture(ref err) => err.fmt(f),
+            AtlasError::OutOfBounds(ref name) => write!(f, "region `{}` lies outside the atlas image", name),
+        }
+    }
+}
+
+impl Error for AtlasError {
+    fn description(&self) -> &str {
+        match *self {
+            AtlasError::Io(ref err) => err.description(),
+            AtlasError::Parse(ref err) => err.description(),
+            AtlasError::Texture(ref err) => err.description(),
+            AtlasError::OutOfBounds(_) => "region lies outside the atlas image",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            AtlasError::Io(ref err) => Some(err),
+            AtlasError::Parse(ref err) => Some(err),
+            AtlasError::Texture(ref err) => Some(err),
+            AtlasError::OutOfBounds(_) => None,
+        }
+    }
+}
+
+/// Loads an image file into a texture, with the first row of the image
+/// at the top.
+pub fn load_texture<P: AsRef<Path>>(window: &Display, path: P) -> Result<Texture2d, TextureError> {
+    use glium::texture::RawImage2d;
+
+    let image = try!(image::open(path)).to_rgba();
+    let dimensions = image.dimensions();
+    let raw = RawImage2d::from_raw_rgba_reversed(image.into_raw(), dimensions);
+
+    Ok(try!(Texture2d::new(window, raw)))
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Image(ImageError),
+    Upload(TextureCreationError),
+}
+
+impl From<ImageError> for TextureError {
+    fn from(err: ImageError) -> Self {
+        TextureError::Image(err)
+    }
+}
+
+impl From<TextureCreationError> for TextureError {
+    fn from(err: TextureCreationError) -> Self {
+        TextureError::Upload(err)
+    }
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextureError::Image(ref err) => err.fmt(f),
+            TextureError::Upload(ref err) => err.fmt(f)
+        }
+    }
+}
+
+impl Error for TextureError {
+    fn description(&self) -> &str {
+        match *self {
+            TextureError::Image(ref err) => err.description(),
+            TextureError::Upload(ref err) => err.description()
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            TextureError::Image(ref err) => Some(err),
+            TextureError::Upload(ref err) => Some(err)
+        }
+    }
+}
+
+/// How the game's internal resolution is fitted into the window when
+/// their aspect ratios or sizes differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScaleMode {
+    /// Scale uniformly to the largest size that fits, leaving bars on
+    /// the sides that don't fill.
+    #[serde(rename = "letterbox")]
+    Letterbox,
+    /// Stretch to cover the whole window.
+    #[serde(rename = "expand")]
+    Expand,
+    /// Scale uniformly by the largest whole-number factor that fits,
+    /// keeping pixel art crisp. Windows smaller than the resolution fall
+    /// back to letterboxing.
+    #[serde(rename = "integer")]
+    Integer,
+}
+
+impl Default for ScaleMode {
+    fn default() -> Self {
+        ScaleMode::Letterbox
+    }
+}
+
+/// How a renderable's colors are combined with what is already drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// Overwrite, ignoring alpha.
+    #[serde(rename = "opaque")]
+    Opaque,
+    /// Blend by the source alpha, for colors that are not premultiplied.
+    #[serde(rename = "alpha")]
+    Alpha,
+    /// Blend colors that have already been multiplied by their alpha,
+    /// which avoids dark fringes around filtered textures.
+    #[serde(rename = "premultiplied")]
+    Premultiplied,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Alpha
+    }
+}
+
+impl BlendMode {
+    fn to_blend(&self) -> Blend {
+        use glium::{BlendingFunction, LinearBlendingFactor};
+
+        match *self {
+            BlendMode::Opaque => Blend::default(),
+            BlendMode::Alpha => Blend::alpha_blending(),
+            BlendMode::Premultiplied => {
+                let function = BlendingFunction::Addition {
+                    source: LinearBlendingFactor::One,
+                    destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                };
+
+                Blend { color: function, alpha: function, constant_value: (0.0, 0.0, 0.0, 0.0) }
+            },
+        }
+    }
+}
+
+/// The region of the window that the game is drawn into.
+#[derive(Debug, Clone, Copy)]
+pub struct Viewport {
+    resolution: (u32, u32),
+    mode: ScaleMode,
+    surface_size: Option<(u32, u32)>,
+}
+
+impl Viewport {
+    pub fn new(resolution: (u32, u32), mode: ScaleMode) -> Self {
+        Viewport {
+            resolution: resolution,
+            mode: mode,
+            surface_size: None,
+        }
+    }
+
+    /// Lays the game out for a surface of this size rather than the size
+    /// the surface reports, for contexts that don't report it reliably.
+    /// `None` goes back to asking the surface.
+    pub fn set_surface_size(&mut self, size: Option<(u32, u32)>) {
+        self.surface_size = size;
+    }
+
+    /// The area of a window of the given size that the game occupies.
+    /// Anything laid out inside this rectangle is guaranteed visible.
+    pub fn rect(&self, window_size: (u32, u32)) -> Rect {
+        let (width, height) = window_size;
+        let (res_width, res_height) = self.resolution;
+
+        let fit = (width as f32 / res_width as f32).min(height as f32 / res_height as f32);
+
+        let scale = match self.mode {
+            ScaleMode::Expand => return Rect { left: 0, bottom: 0, width: width, height: height },
+            ScaleMode::Letterbox => fit,
+            ScaleMode::Integer => {
+                let scale = (width / res_width).min(height / res_height);
+                if scale < 1 { fit } else { scale as f32 }
+            },
+        };
+
+        let scaled_width = ((res_width as f32 * scale) as u32).min(width);
+        let scaled_height = ((res_height as f32 * scale) as u32).min(height);
+
+        Rect {
+            left: (width - scaled_width) / 2,
+            bottom: (height - scaled_height) / 2,
+            width: scaled_width,
+            height: scaled_height,
+        }
+    }
+
+    /// Converts an area in game coordinates (origin at the top-left of
+    /// the internal resolution) into window pixels, clamped to the
+    /// viewport. Used to build scissor rectangles. A negative width or
+    /// height extends the area left or up from its corner.
+    pub fn project(&self, area: (Coord, Size), window_size: (u32, u32)) -> Rect {
+        let viewport = self.rect(window_size);
+        let scale_x = viewport.width as f32 / self.resolution.0 as f32;
+        let scale_y = viewport.height as f32 / self.resolution.1 as f32;
+
+        let ((x, y), (w, h)) = area;
+        let (x, w) = if w < 0 { (x + w, -w) } else { (x, w) };
+        let (y, h) = if h < 0 { (y + h, -h) } else { (y, h) };
+        let clamp = |value: i32, bound: u32| if value < 0 { 0 } else { (value as u32).min(bound) };
+
+        let left = clamp(x, self.resolution.0);
+        let right = clamp(x + w, self.resolution.0);
+        let top = clamp(y, self.resolution.1);
+        let bottom = clamp(y + h, self.resolution.1);
+
+        Rect {
+            left: viewport.left + (left as f32 * scale_x) as u32,
+            bottom: viewport.bottom + ((self.resolution.1 - bottom) as f32 * scale_y) as u32,
+            width: ((right - left) as f32 * scale_x) as u32,
+            height: ((bottom - top) as f32 * scale_y) as u32,
+        }
+    }
+
+    /// Converts a point in window pixels (origin at the top-left, as
+    /// reported by cursor events) into game coordinates. These are screen
+    /// coordinates; `Camera::screen_to_world` takes them into the world.
+    #[allow(dead_code)] // For mouse input, which the game doesn't handle yet.
+    pub fn to_game(&self, point: (f32, f32), window_size: (u32, u32)) -> (f32, f32) {
+        let (left, top, scale_x, scale_y) = self.placement(window_size);
+
+        ((point.0 - left) / scale_x, (point.1 - top) / scale_y)
+    }
+
+    /// Converts a point in game coordinates into window pixels with the
+    /// origin at the top-left.
+    #[allow(dead_code)] // For mouse input, which the game doesn't handle yet.
+    pub fn to_window(&self, point: (f32, f32), window_size: (u32, u32)) -> (f32, f32) {
+        let (left, top, scale_x, scale_y) = self.placement(window_size);
+
+        (left + point.0 * scale_x, top + point.1 * scale_y)
+    }
+
+    /// The top-left corner of the viewport in window pixels and the
+    /// scale from game coordinates to window pixels on each axis.
+    fn placement(&self, window_size: (u32, u32)) -> (f32, f32, f32, f32) {
+        let viewport = self.rect(window_size);
+        let top = window_size.1 - (viewport.bottom + viewport.height);
+
+        (viewport.left as f32,
+         top as f32,
+         viewport.width as f32 / self.resolution.0 as f32,
+         viewport.height as f32 / self.resolution.1 as f32)
+    }
+}
+
+/// An RGBA color with components from 0 to 1. Configuration files write
+/// it as a hex string, such as `"#ff8800"` or `"#ff880080"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color { r: r, g: g, b: b, a: a }
+    }
+
+    /// Parses `#rrggbb` or `#rrggbbaa`, where the `#` is optional and a
+    /// missing alpha is opaque.
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let hex = if hex.starts_with('#') { &hex[1..] } else { hex };
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_digit(16)) { return None }
+
+        let channel = |index: usize| u8::from_str_radix(&hex[index * 2 .. index * 2 + 2], 16).unwrap() as f32 / 255.0;
+        let alpha = if hex.len() == 8 { channel(3) } else { 1.0 };
+
+        Some(Color::rgba(channel(0), channel(1), channel(2), alpha))
+    }
+
+    pub fn to_hex(&self) -> String {
+        let byte = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+
+        format!("#{:02x}{:02x}{:02x}{:02x}", byte(self.r), byte(self.g), byte(self.b), byte(self.a))
+    }
+}
+
+const WHITE: Color = Color { r: 1.0, g: 1.0, b: 1.0, a: 1.0 };
+
+impl From<[f32; 4]> for Color {
+    fn from(rgba: [f32; 4]) -> Self {
+        Color::rgba(rgba[0], rgba[1], rgba[2], rgba[3])
+    }
+}
+
+impl From<Color> for [f32; 4] {
+    fn from(color: Color) -> Self {
+        [color.r, color.g, color.b, color.a]
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl Deserialize for Color {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let hex = try!(String::deserialize(deserializer));
+        Color::from_hex(&hex).ok_or_else(|| D::Error::invalid_value(&format!("`{}` is not a hex color", hex)))
+    }
+}
+
+/// A value that can be bound to a shader uniform through a `Material`.
+#[derive(Clone)]
+#[allow(dead_code)] // Float, Vec2 and Vec3 are for custom shaders.
+pub enum MaterialValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Texture(Rc<Texture2d>),
 }
 
-implement_vertex!(Vertex, position);
+impl MaterialValue {
+    fn as_uniform_value(&self) -> UniformValue {
+        match *self {
+            MaterialValue::Float(value) => UniformValue::Float(value),
+            MaterialValue::Vec2(value) => UniformValue::Vec2(value),
+            MaterialValue::Vec3(value) => UniformValue::Vec3(value),
+            MaterialValue::Vec4(value) => UniformValue::Vec4(value),
+            MaterialValue::Texture(ref texture) => UniformValue::Texture2d(&**texture, None),
+        }
+    }
+}
 
-pub struct Quad<'window> {
-    position: Coord,
-    size: Size,
-    window: &'window Display,
-    vertices: VertexBuffer<Vertex>,
-    indices: NoIndices,
-    program: Program,
+/// Named uniform values supplied to a renderable's shader on every draw.
+#[derive(Clone, Default)]
+pub struct Material {
+    values: HashMap<String, MaterialValue>,
 }
 
-impl<'window> Quad<'window> {
-    pub fn new(window: &'window Display, origin: Coord, size: Size) -> Self {
-        use glium::index::PrimitiveType;
+impl Material {
+    pub fn new() -> Self {
+        Material { values: HashMap::new() }
+    }
 
-        let p2u = pixel_to_unit;
-        let window_size = window.get_window().unwrap().get_inner_size_pixels().unwrap();
-        let width = window_size.0;
-        let height = window_size.1;
+    pub fn set(&mut self, name: &str, value: MaterialValue) {
+        self.values.insert(name.to_owned(), value);
+    }
 
-        let vertices = [
-            Vertex { position: [p2u(origin.0, width), p2u(height as i32 - origin.1, height)] },
-            Vertex { position: [p2u(origin.0 + size.0, width), p2u(height as i32 - origin.1, height)] },
-            Vertex { position: [p2u(origin.0, width), p2u(height as i32 - origin.1 - size.1, height)] },
-            Vertex { position: [p2u(origin.0 + size.0, width), p2u(height as i32 - origin.1 - size.1, height)] },
-        ];
+    pub fn get(&self, name: &str) -> Option<&MaterialValue> {
+        self.values.get(name)
+    }
 
-        Quad {
-            position: origin,
-            size: size,
-            window: window,
-            vertices: VertexBuffer::new(window, &vertices).unwrap(),
-            indices: NoIndices(PrimitiveType::TriangleStrip),
-            program: Program::from_source(window, vertex_shader(), fragment_shader(), None).unwrap(),
+    /// Checks that every value is declared by `program` with a
+    /// compatible type.
+    pub fn validate(&self, program: &Program) -> Result<(), MaterialError> {
+        for (name, value) in &self.values {
+            match program.get_uniform(name) {
+                Some(uniform) if value.as_uniform_value().is_usable_with(&uniform.ty) => { },
+                Some(uniform) => return Err(MaterialError::TypeMismatch(name.clone(), uniform.ty)),
+                None => return Err(MaterialError::UnknownUniform(name.clone())),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Uniforms for Material {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
+        for (name, value) in &self.values {
+            visit(name, value.as_uniform_value());
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MaterialError {
+    UnknownUniform(String),
+    TypeMismatch(String, UniformType),
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MaterialError::UnknownUniform(ref name) =>
+                write!(f, "uniform `{}` is not declared by the shader", name),
+            MaterialError::TypeMismatch(ref name, ref ty) =>
+                write!(f, "uniform `{}` does not match the shader's type {:?}", name, ty),
         }
     }
+}
 
-    pub fn translate(&mut self, direction: Direction) {
-        match direction {
-            Direction::Up => self.position.1 -= 32,
-            Direction::Down => self.position.1 += 32,
-            Direction::Left => self.position.0 -= 32,
-            Direction::Right => self.position.0 += 32,
+impl Error for MaterialError {
+    fn description(&self) -> &str {
+        match *self {
+            MaterialError::UnknownUniform(_) => "uniform is not declared by the shader",
+            MaterialError::TypeMismatch(..) => "uniform does not match the shader's type",
         }
+    }
+}
+
+/// The sampler uniform that textured quads read from.
+const TEXTURE_UNIFORM: &'static str = "tex";
+/// The fill color of solid quads.
+const COLOR_UNIFORM: &'static str = "color";
 
-        let p2u = pixel_to_unit;
-        let width = 800u32;
-        let height = 600u32;
-        let size = (50, 50);
+const VERTEX_SHADER: &'static str = "quad.vert";
+const SOLID_FRAGMENT_SHADER: &'static str = "solid.frag";
+const TEXTURED_FRAGMENT_SHADER: &'static str = "textured.frag";
+const VERTEX_COLOR_FRAGMENT_SHADER: &'static str = "vertex_color.frag";
 
-        let vertices = [
-            Vertex { position: [p2u(self.position.0, width), p2u(height as i32 - self.position.1, height)] },
-            Vertex { position: [p2u(self.position.0 + size.0, width), p2u(height as i32 - self.position.1, height)] },
-            Vertex { position: [p2u(self.position.0, width), p2u(height as i32 - self.position.1 - size.1, height)] },
-            Vertex { position: [p2u(self.position.0 + size.0, width), p2u(height as i32 - self.position.1 - size.1, height)] },
-        ];
+/// The compiled shader programs that quads are drawn with, shared
+/// between every quad that uses them.
+pub struct Shaders {
+    solid: Rc<Program>,
+    textured: Rc<Program>,
+    vertex_color: Rc<Program>,
+}
+
+impl Shaders {
+    /// Reads and compiles the GLSL sources in `dir`.
+    pub fn load<P: AsRef<Path>>(window: &Display, dir: P) -> Result<Shaders, GraphicsError> {
+        let dir = dir.as_ref();
+        let vertex = try!(read_shader(dir.join(VERTEX_SHADER)));
+        let solid = try!(read_shader(dir.join(SOLID_FRAGMENT_SHADER)));
+        let textured = try!(read_shader(dir.join(TEXTURED_FRAGMENT_SHADER)));
+        let vertex_color = try!(read_shader(dir.join(VERTEX_COLOR_FRAGMENT_SHADER)));
+
+        Ok(Shaders {
+            solid: Rc::new(try!(Program::from_source(window, &vertex, &solid, None))),
+            textured: Rc::new(try!(Program::from_source(window, &vertex, &textured, None))),
+            vertex_color: Rc::new(try!(Program::from_source(window, &vertex, &vertex_color, None))),
+        })
+    }
+}
+
+fn read_shader(path: PathBuf) -> Result<String, GraphicsError> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut source = String::new();
+
+    match File::open(&path).and_then(|mut file| file.read_to_string(&mut source)) {
+        Ok(_) => Ok(source),
+        Err(err) => Err(GraphicsError::Io(path, err)),
+    }
+}
+
+#[derive(Debug)]
+pub enum GraphicsError {
+    Io(PathBuf, io::Error),
+    Compile(ProgramCreationError),
+    Texture(TextureCreationError),
+    RenderBuffer(RenderBufferCreationError),
+    Framebuffer(ValidationError),
+}
+
+impl From<ProgramCreationError> for GraphicsError {
+    fn from(err: ProgramCreationError) -> Self {
+        GraphicsError::Compile(err)
+    }
+}
+
+impl From<TextureCreationError> for GraphicsError {
+    fn from(err: TextureCreationError) -> Self {
+        GraphicsError::Texture(err)
+    }
+}
 
-        self.vertices = VertexBuffer::new(self.window, &vertices).unwrap();
+impl From<RenderBufferCreationError> for GraphicsError {
+    fn from(err: RenderBufferCreationError) -> Self {
+        GraphicsError::RenderBuffer(err)
     }
 }
 
-pub fn pixel_to_unit(pixel: i32, bound: u32) -> f32 {
-    let origin = (bound as f32) / 2f32;
-    (pixel as f32 - origin) / origin
+impl From<ValidationError> for GraphicsError {
+    fn from(err: ValidationError) -> Self {
+        GraphicsError::Framebuffer(err)
+    }
 }
 
-fn vertex_shader() -> &'static str {
-    r#"
-        #version 140
-        in vec2 position;
-        void main() {
-            gl_Position = vec4(position, 0.0, 1.0);
+impl fmt::Display for GraphicsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GraphicsError::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            GraphicsError::Compile(ref err) => err.fmt(f),
+            GraphicsError::Texture(ref err) => err.fmt(f),
+            GraphicsError::RenderBuffer(ref err) => err.fmt(f),
+            GraphicsError::Framebuffer(ref err) => err.fmt(f)
         }
-    "#
+    }
 }
 
-fn fragment_shader() -> &'static str {
-    r#"
-        #version 140
-        out vec4 color;
-        void main() {
-            color = vec4(1.0, 0.0, 0.0, 1.0);
+impl Error for GraphicsError {
+    fn description(&self) -> &str {
+        match *self {
+            GraphicsError::Io(_, ref err) => err.description(),
+            GraphicsError::Compile(ref err) => err.description(),
+            GraphicsError::Texture(ref err) => err.description(),
+            GraphicsError::RenderBuffer(ref err) => err.description(),
+            GraphicsError::Framebuffer(ref err) => err.description()
         }
-    "#
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            GraphicsError::Io(_, ref err) => Some(err),
+            GraphicsError::Compile(ref err) => Some(err),
+            GraphicsError::Texture(ref err) => Some(err),
+            GraphicsError::RenderBuffer(ref err) => Some(err),
+            GraphicsError::Framebuffer(ref err) => Some(err)
+        }
+    }
+}
+
+/// An offscreen image that can be drawn into like the window and then
+/// used as a texture, for example by a `Quad::textured` that presents
+/// it, for post-processing, minimaps or screenshots.
+pub struct RenderTarget {
+    color: Rc<Texture2d>,
+    stencil: StencilRenderBuffer,
+}
+
+#[allow(dead_code)] // Nothing draws offscreen yet.
+impl RenderTarget {
+    pub fn new(window: &Display, size: (u32, u32)) -> Result<RenderTarget, GraphicsError> {
+        use glium::texture::StencilFormat;
+
+        let (width, height) = size;
+
+        Ok(RenderTarget {
+            color: Rc::new(try!(Texture2d::empty(window, width, height))),
+            stencil: try!(StencilRenderBuffer::new(window, StencilFormat::I8, width, height)),
+        })
+    }
+
+    /// A surface that draws into this target. `Render` works on it just
+    /// as it does on a `Frame`.
+    pub fn surface(&self, window: &Display) -> Result<SimpleFrameBuffer, GraphicsError> {
+        Ok(try!(SimpleFrameBuffer::with_stencil_buffer(window, &*self.color, &self.stencil)))
+    }
+
+    /// The image drawn so far, to sample from once drawing is done.
+    pub fn texture(&self) -> &Rc<Texture2d> {
+        &self.color
+    }
 }
 
 pub trait Render {
-    fn render<'entity, R: Renderable<'entity> + 'entity>(&mut self, renderable: &'entity R);
+    fn render<'entity, R>(&mut self, renderable: &'entity R, viewport: &Viewport, camera: &Camera)
+        where R: Renderable<'entity> + ?Sized + 'entity;
+
+    /// Writes the shape of `mask` into the stencil buffer without drawing
+    /// any color. Masks accumulate until the stencil buffer is cleared.
+    #[allow(dead_code)] // The demo scene has nothing to mask.
+    fn render_mask<'entity, R>(&mut self, mask: &'entity R, viewport: &Viewport, camera: &Camera)
+        where R: Renderable<'entity> + ?Sized + 'entity;
+
+    /// Draws `renderable` only where a mask has been written.
+    #[allow(dead_code)] // Only useful after `render_mask`.
+    fn render_masked<'entity, R>(&mut self, renderable: &'entity R, viewport: &Viewport, camera: &Camera)
+        where R: Renderable<'entity> + ?Sized + 'entity;
+}
+
+impl<S: Surface> Render for S {
+    fn render<'entity, R>(&mut self, renderable: &'entity R, viewport: &Viewport, camera: &Camera)
+        where R: Renderable<'entity> + ?Sized + 'entity
+    {
+        draw(self, renderable, viewport, camera, Default::default());
+    }
+
+    fn render_mask<'entity, R>(&mut self, mask: &'entity R, viewport: &Viewport, camera: &Camera)
+        where R: Renderable<'entity> + ?Sized + 'entity
+    {
+        let params = DrawParameters {
+            stencil: stencil(StencilTest::AlwaysPass, StencilOperation::Replace),
+            color_mask: (false, false, false, false),
+            .. Default::default()
+        };
+
+        draw(self, mask, viewport, camera, params);
+    }
+
+    fn render_masked<'entity, R>(&mut self, renderable: &'entity R, viewport: &Viewport, camera: &Camera)
+        where R: Renderable<'entity> + ?Sized + 'entity
+    {
+        let params = DrawParameters {
+            stencil: stencil(StencilTest::IfEqual { mask: !0 }, StencilOperation::Keep),
+            .. Default::default()
+        };
+
+        draw(self, renderable, viewport, camera, params);
+    }
+}
+
+/// The uniform the vertex shader reads the camera's transform from.
+const VIEW_PROJECTION_UNIFORM: &'static str = "view_projection";
+
+/// Looks onto the game world, mapping game coordinates (pixels, with the
+/// origin at the top-left) into normalised device space. The transform
+/// is applied on the GPU, so moving or zooming the camera leaves vertex
+/// buffers untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    size: (f32, f32),
+    centre: (f32, f32),
+    zoom: f32,
+}
+
+impl Camera {
+    /// Creates a camera showing an area of `size` game pixels with its
+    /// top-left corner at the origin.
+    pub fn new(size: (u32, u32)) -> Self {
+        let size = (size.0 as f32, size.1 as f32);
+
+        Camera {
+            size: size,
+            centre: (size.0 / 2.0, size.1 / 2.0),
+            zoom: 1.0,
+        }
+    }
+
+    #[allow(dead_code)] // The demo camera never moves.
+    pub fn centre(&self) -> (f32, f32) {
+        self.centre
+    }
+
+    /// Centres the view on `target`, such as the player's position.
+    #[allow(dead_code)] // The demo camera never moves.
+    pub fn look_at(&mut self, target: (f32, f32)) {
+        self.centre = target;
+    }
+
+    #[allow(dead_code)] // The demo camera never moves.
+    pub fn translate(&mut self, offset: (f32, f32)) {
+        self.centre = (self.centre.0 + offset.0, self.centre.1 + offset.1);
+    }
+
+    #[allow(dead_code)] // The demo camera never zooms.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Sets the magnification, about the centre of the view. Values above
+    /// one zoom in. Returns false, leaving the zoom alone, unless `zoom` is
+    /// positive and finite.
+    #[allow(dead_code)] // The demo camera never zooms.
+    pub fn set_zoom(&mut self, zoom: f32) -> bool {
+        if !(zoom > 0.0 && zoom.is_finite()) { return false }
+
+        self.zoom = zoom;
+        true
+    }
+
+    /// Converts a point in world coordinates into screen coordinates, with
+    /// the origin at the top-left of the game's resolution.
+    pub fn world_to_screen(&self, point: (f32, f32)) -> (f32, f32) {
+        ((point.0 - self.centre.0) * self.zoom + self.size.0 / 2.0,
+         (point.1 - self.centre.1) * self.zoom + self.size.1 / 2.0)
+    }
+
+    /// Converts a point in screen coordinates, such as one from
+    /// `Viewport::to_game`, into world coordinates.
+    #[allow(dead_code)] // For mouse input, which the game doesn't handle yet.
+    pub fn screen_to_world(&self, point: (f32, f32)) -> (f32, f32) {
+        ((point.0 - self.size.0 / 2.0) / self.zoom + self.centre.0,
+         (point.1 - self.size.1 / 2.0) / self.zoom + self.centre.1)
+    }
+
+    /// The smallest whole-pixel area on screen that covers `area` in world
+    /// coordinates.
+    pub fn area_to_screen(&self, area: (Coord, Size)) -> (Coord, Size) {
+        let ((x, y), (width, height)) = area;
+        let top_left = self.world_to_screen((x as f32, y as f32));
+        let bottom_right = self.world_to_screen(((x + width) as f32, (y + height) as f32));
+
+        let (left, top) = (top_left.0.floor() as i32, top_left.1.floor() as i32);
+        let (right, bottom) = (bottom_right.0.ceil() as i32, bottom_right.1.ceil() as i32);
+
+        ((left, top), (right - left, bottom - top))
+    }
+
+    /// The area in view as its top-left and bottom-right corners in game
+    /// coordinates.
+    pub fn visible_area(&self) -> ((f32, f32), (f32, f32)) {
+        let half_width = self.size.0 / (2.0 * self.zoom);
+        let half_height = self.size.1 / (2.0 * self.zoom);
+
+        ((self.centre.0 - half_width, self.centre.1 - half_height),
+         (self.centre.0 + half_width, self.centre.1 + half_height))
+    }
+
+    /// Whether any of `area` lies within `margin` pixels of the view.
+    pub fn is_visible(&self, area: (Coord, Size), margin: f32) -> bool {
+        let ((left, top), (right, bottom)) = self.visible_area();
+        let ((x, y), (width, height)) = area;
+        let (x, y, width, height) = (x as f32, y as f32, width as f32, height as f32);
+
+        x + width >= left - margin && x <= right + margin && y + height >= top - margin && y <= bottom + margin
+    }
+
+    /// The column-major matrix taking game coordinates to normalised
+    /// device space, with y flipped so that it points down.
+    pub fn view_projection(&self) -> [[f32; 4]; 4] {
+        let scale_x = 2.0 * self.zoom / self.size.0;
+        let scale_y = -2.0 * self.zoom / self.size.1;
+
+        [
+            [scale_x, 0.0, 0.0, 0.0],
+            [0.0, scale_y, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-scale_x * self.centre.0, -scale_y * self.centre.1, 0.0, 1.0],
+        ]
+    }
+}
+
+/// The uniform the vertex shader reads a renderable's own transform from.
+const MODEL_UNIFORM: &'static str = "model";
+
+/// The uniforms for a single draw: the renderable's material and model
+/// transform, and the camera's transform.
+struct DrawUniforms<'a> {
+    material: &'a Material,
+    model: [[f32; 4]; 4],
+    view_projection: [[f32; 4]; 4],
+}
+
+impl<'a> Uniforms for DrawUniforms<'a> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut visit: F) {
+        self.material.visit_values(&mut visit);
+        visit(MODEL_UNIFORM, UniformValue::Mat4(self.model));
+        visit(VIEW_PROJECTION_UNIFORM, UniformValue::Mat4(self.view_projection));
+    }
 }
 
-impl Render for Frame {
-    fn render<'entity, R: Renderable<'entity> + 'entity>(&mut self, renderable: &'entity R) {
-        use glium::uniforms::EmptyUniforms;
+/// How far outside the camera's view, in game pixels, a renderable may
+/// be before it is culled.
+const CULL_MARGIN: f32 = 32.0;
+
+/// The value written to the stencil buffer by masks.
+const MASK_REFERENCE: i32 = 1;
 
-        let vertices = renderable.vertices();
-        let indices = renderable.indices();
-        let program = renderable.program();
+fn stencil(test: StencilTest, operation: StencilOperation) -> Stencil {
+    Stencil {
+        test_clockwise: test,
+        reference_value_clockwise: MASK_REFERENCE,
+        depth_pass_operation_clockwise: operation,
+        test_counter_clockwise: test,
+        reference_value_counter_clockwise: MASK_REFERENCE,
+        depth_pass_operation_counter_clockwise: operation,
+        .. Default::default()
+    }
+}
 
-        self.draw(vertices, indices, program, &EmptyUniforms, &Default::default()).unwrap();
+fn draw<'entity, S, R>(surface: &mut S, renderable: &'entity R, viewport: &Viewport, camera: &Camera,
+                       mut params: DrawParameters)
+    where S: Surface, R: Renderable<'entity> + ?Sized + 'entity
+{
+    if let Some(bounds) = renderable.bounds() {
+        if !camera.is_visible(bounds, CULL_MARGIN) { return }
     }
+
+    let vertices = renderable.vertices();
+    let indices = renderable.indices();
+    let program = renderable.program();
+    let uniforms = DrawUniforms {
+        material: renderable.material(),
+        model: renderable.model(),
+        view_projection: camera.view_projection(),
+    };
+
+    let window_size = viewport.surface_size.unwrap_or_else(|| surface.get_dimensions());
+    params.viewport = Some(viewport.rect(window_size));
+    params.blend = renderable.blend_mode().to_blend();
+    params.scissor = renderable.scissor().map(|area| viewport.project(camera.area_to_screen(area), window_size));
+
+    surface.draw(vertices, indices.buffer(), program, &uniforms, &params).unwrap();
 }
 
 pub trait Renderable<'entity> {
     fn vertices(&'entity self) -> &'entity VertexBuffer<Vertex>;
-    fn indices(&'entity self) -> &'entity NoIndices;
+    fn indices(&'entity self) -> &'entity Indices;
     fn program(&'entity self) -> &'entity Program;
+    fn material(&'entity self) -> &'entity Material;
+
+    /// The area outside of which nothing is drawn, if any.
+    fn scissor(&'entity self) -> Option<(Coord, Size)> {
+        None
+    }
+
+    /// The area covered in game coordinates, used to skip drawing what
+    /// the camera cannot see. `None` is always drawn.
+    fn bounds(&'entity self) -> Option<(Coord, Size)> {
+        None
+    }
+
+    /// The transform from the vertices' space into game coordinates.
+    fn model(&'entity self) -> [[f32; 4]; 4] {
+        IDENTITY
+    }
+
+    /// Where this is drawn in the frame: lower layers are drawn first,
+    /// underneath higher ones.
+    fn layer(&'entity self) -> i32 {
+        ENTITY_LAYER
+    }
+
+    /// How this is combined with what is already drawn.
+    fn blend_mode(&'entity self) -> BlendMode {
+        BlendMode::default()
+    }
+}
+
+#[allow(dead_code)] // Everything in the demo scene is on the entity layer.
+pub const BACKGROUND_LAYER: i32 = -100;
+pub const ENTITY_LAYER: i32 = 0;
+#[allow(dead_code)] // Everything in the demo scene is on the entity layer.
+pub const UI_LAYER: i32 = 100;
+
+/// Sorts renderables into drawing order by layer, keeping the order they
+/// were given in within a layer.
+pub fn sort_by_layer<'entity>(renderables: &mut [&'entity Renderable<'entity>]) {
+    renderables.sort_by_key(|renderable| {
+        let renderable = *renderable;
+        renderable.layer()
+    });
+}
+
+impl<'entity> Renderable<'entity> for Quad {
+    fn vertices(&'entity self) -> &'entity VertexBuffer<Vertex> {
+        &self.vertices
+    }
+
+    fn indices(&'entity self) -> &'entity Indices {
+        &self.indices
+    }
+
+    fn program(&'entity self) -> &'entity Program {
+        &self.program
+    }
+
+    fn material(&'entity self) -> &'entity Material {
+        &self.material
+    }
+
+    fn scissor(&'entity self) -> Option<(Coord, Size)> {
+        self.clip
+    }
+
+    fn bounds(&'entity self) -> Option<(Coord, Size)> {
+        Some(Quad::bounds(self))
+    }
+
+    fn model(&'entity self) -> [[f32; 4]; 4] {
+        Quad::model(self)
+    }
+
+    fn layer(&'entity self) -> i32 {
+        self.layer
+    }
+
+    fn blend_mode(&'entity self) -> BlendMode {
+        self.blend_mode
+    }
 }
 
-impl<'entity, 'window> Renderable<'entity> for Quad<'window> {
+impl<'entity> Renderable<'entity> for QuadBatch {
     fn vertices(&'entity self) -> &'entity VertexBuffer<Vertex> {
         &self.vertices
     }
 
-    fn indices(&'entity self) -> &'entity NoIndices {
+    fn indices(&'entity self) -> &'entity Indices {
         &self.indices
     }
 
     fn program(&'entity self) -> &'entity Program {
         &self.program
     }
+
+    fn material(&'entity self) -> &'entity Material {
+        &self.material
+    }
+
+    fn layer(&'entity self) -> i32 {
+        self.layer
+    }
+
+    fn blend_mode(&'entity self) -> BlendMode {
+        self.blend_mode
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use quickcheck::TestResult;
+
+    /// Applies a column-major transform to a point, as the vertex
+    /// shader does.
+    fn transform(matrix: [[f32; 4]; 4], point: (f32, f32)) -> (f32, f32) {
+        (matrix[0][0] * point.0 + matrix[1][0] * point.1 + matrix[3][0],
+         matrix[0][1] * point.0 + matrix[1][1] * point.1 + matrix[3][1])
+    }
+
+    #[test]
+    fn test_camera_view_projection() {
+        let mut camera = Camera::new((512, 256));
+        let corners = |camera: &Camera| (transform(camera.view_projection(), (0.0, 0.0)),
+                                         transform(camera.view_projection(), (512.0, 256.0)));
+
+        assert_eq!(((-1.0, 1.0), (1.0, -1.0)), corners(&camera));
+        assert_eq!((0.5, 0.0), transform(camera.view_projection(), (384.0, 128.0)));
+
+        camera.translate((256.0, 0.0));
+        assert_eq!(((-2.0, 1.0), (0.0, -1.0)), corners(&camera));
+
+        camera.look_at((256.0, 128.0));
+        assert!(camera.set_zoom(2.0));
+        assert_eq!(((-2.0, 2.0), (2.0, -2.0)), corners(&camera));
+
+        assert!(!camera.set_zoom(0.0));
+        assert!(!camera.set_zoom(-1.0));
+        assert!(!camera.set_zoom(::std::f32::NAN));
+        assert!(!camera.set_zoom(::std::f32::INFINITY));
+        assert_eq!(2.0, camera.zoom());
+    }
+
+    #[test]
+    fn test_camera_area_to_screen() {
+        let mut camera = Camera::new((512, 256));
+        assert_eq!(((32, 32), (64, 32)), camera.area_to_screen(((32, 32), (64, 32))));
+
+        camera.translate((100.0, 0.0));
+        assert_eq!(((-68, 32), (64, 32)), camera.area_to_screen(((32, 32), (64, 32))));
+
+        camera.look_at((256.0, 128.0));
+        camera.set_zoom(2.0);
+        assert_eq!(((-192, -64), (128, 64)), camera.area_to_screen(((32, 32), (64, 32))));
+    }
+
+    #[test]
+    fn test_camera_is_visible() {
+        let mut camera = Camera::new((512, 256));
+        let offscreen = ((600, 0), (32, 32));
+
+        assert!(camera.is_visible(((0, 0), (32, 32)), 0.0));
+        assert!(!camera.is_visible(offscreen, 0.0));
+        assert!(camera.is_visible(offscreen, 100.0));
+
+        camera.translate((100.0, 0.0));
+        assert!(camera.is_visible(offscreen, 0.0));
+    }
+
+    #[test]
+    fn test_model_matrix() {
+        assert_eq!((10.0, 20.0), transform(model_matrix((10, 20), (4, 2), 0.0, 1.0), (0.0, 0.0)));
+        assert_eq!((14.0, 22.0), transform(model_matrix((10, 20), (4, 2), 0.0, 1.0), (4.0, 2.0)));
+
+        assert_eq!((8.0, 19.0), transform(model_matrix((10, 20), (4, 2), 0.0, 2.0), (0.0, 0.0)));
+        assert_eq!((12.0, 21.0), transform(model_matrix((10, 20), (4, 2), 0.0, 2.0), (2.0, 1.0)));
+
+        let close = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4;
+        let quarter_turn = model_matrix((10, 20), (4, 2), ::std::f32::consts::FRAC_PI_2, 1.0);
+        assert!(close((13.0, 19.0), transform(quarter_turn, (0.0, 0.0))));
+        assert!(close((12.0, 21.0), transform(quarter_turn, (2.0, 1.0))));
+    }
+
+    #[test]
+    fn test_quad_vertices() {
+        let red = Color::rgba(1.0, 0.0, 0.0, 1.0);
+        let vertices = quad_vertices((200, 0), (200, 300), TexRect::full(), [WHITE, WHITE, WHITE, red]);
+
+        assert_eq!([200.0, 0.0], vertices[0].position);
+        assert_eq!([400.0, 300.0], vertices[3].position);
+        assert_eq!([0.0, 1.0], vertices[0].tex_coords);
+        assert_eq!([1.0, 0.0], vertices[3].tex_coords);
+        assert_eq!([1.0, 1.0, 1.0, 1.0], vertices[0].color);
+        assert_eq!([1.0, 0.0, 0.0, 1.0], vertices[3].color);
+    }
+
+    #[test]
+    fn test_batch_indices() {
+        assert_eq!(vec![0, 1, 2, 1, 3, 2, 4, 5, 6, 5, 7, 6], batch_indices(2));
+        assert!(batch_indices(0).is_empty());
+    }
+
+    #[test]
+    fn test_read_shader() {
+        assert!(read_shader(Path::new("assets/shaders").join(VERTEX_SHADER)).is_ok());
+
+        match read_shader(PathBuf::from("assets/shaders/missing.frag")) {
+            Err(GraphicsError::Io(path, _)) => assert_eq!(Path::new("assets/shaders/missing.frag"), path),
+            _ => panic!("expected an IO error"),
+        }
+    }
+
+    #[test]
+    fn test_color_from_hex() {
+        assert_eq!(Some(Color::rgba(1.0, 0.0, 0.0, 1.0)), Color::from_hex("#ff0000"));
+        assert_eq!(Some(Color::rgba(0.0, 1.0, 0.0, 0.0)), Color::from_hex("00ff0000"));
+        assert_eq!(None, Color::from_hex("#ff00"));
+        assert_eq!(None, Color::from_hex("#gg0000"));
+
+        let color = Color::from([0.2, 0.4, 0.6, 0.8]);
+        assert_eq!(Some(color), Color::from_hex(&color.to_hex()));
+        assert_eq!("#336699cc", color.to_hex());
+    }
+
+    #[test]
+    fn test_region_to_tex_rect() {
+        let region = Region { x: 32, y: 0, width: 32, height: 64 };
+
+        assert_eq!(TexRect { left: 0.25, right: 0.5, bottom: 0.5, top: 1.0 }, region.to_tex_rect((128, 128)));
+    }
+
+    #[test]
+    fn test_region_fits() {
+        assert!(Region { x: 32, y: 0, width: 32, height: 64 }.fits((64, 64)));
+        assert!(!Region { x: 32, y: 0, width: 33, height: 64 }.fits((64, 64)));
+        assert!(!Region { x: 0, y: 1, width: 32, height: 64 }.fits((64, 64)));
+        assert!(!Region { x: u32::max_value(), y: 0, width: 1, height: 1 }.fits((64, 64)));
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        use glium::{BlendingFunction, LinearBlendingFactor};
+
+        assert_eq!(Blend::default(), BlendMode::Opaque.to_blend());
+        assert_eq!(Blend::alpha_blending(), BlendMode::Alpha.to_blend());
+        assert_eq!(BlendingFunction::Addition { source: LinearBlendingFactor::One,
+                                                destination: LinearBlendingFactor::OneMinusSourceAlpha },
+                   BlendMode::Premultiplied.to_blend().color);
+    }
+
+    #[test]
+    fn test_viewport_letterbox() {
+        let viewport = Viewport::new((640, 480), ScaleMode::Letterbox);
+
+        assert_eq!(Rect { left: 0, bottom: 0, width: 640, height: 480 }, viewport.rect((640, 480)));
+        assert_eq!(Rect { left: 160, bottom: 0, width: 640, height: 480 }, viewport.rect((960, 480)));
+        assert_eq!(Rect { left: 0, bottom: 60, width: 320, height: 240 }, viewport.rect((320, 360)));
+    }
+
+    #[test]
+    fn test_viewport_expand() {
+        let viewport = Viewport::new((640, 480), ScaleMode::Expand);
+
+        assert_eq!(Rect { left: 0, bottom: 0, width: 1000, height: 500 }, viewport.rect((1000, 500)));
+    }
+
+    #[test]
+    fn test_viewport_integer() {
+        let viewport = Viewport::new((320, 240), ScaleMode::Integer);
+
+        assert_eq!(Rect { left: 20, bottom: 10, width: 640, height: 480 }, viewport.rect((680, 500)));
+        assert_eq!(Rect { left: 0, bottom: 25, width: 200, height: 150 }, viewport.rect((200, 200)));
+    }
 
     #[test]
-    fn test_pixel_to_unit() {
-        let bound = 800;
+    fn test_viewport_project() {
+        let viewport = Viewport::new((640, 480), ScaleMode::Letterbox);
+
+        assert_eq!(Rect { left: 32, bottom: 416, width: 32, height: 32 },
+                   viewport.project(((32, 32), (32, 32)), (640, 480)));
+        assert_eq!(Rect { left: 224, bottom: 832, width: 64, height: 64 },
+                   viewport.project(((32, 32), (32, 32)), (1600, 960)));
+        assert_eq!(Rect { left: 0, bottom: 448, width: 32, height: 32 },
+                   viewport.project(((-32, 0), (64, 32)), (640, 480)));
+
+        assert_eq!(Rect { left: 32, bottom: 416, width: 32, height: 32 },
+                   viewport.project(((64, 64), (-32, -32)), (640, 480)));
+        assert_eq!(Rect { left: 0, bottom: 480, width: 0, height: 0 },
+                   viewport.project(((0, 0), (-32, -32)), (640, 480)));
+    }
+
+    fn scale_mode(index: u8) -> ScaleMode {
+        match index % 3 {
+            0 => ScaleMode::Letterbox,
+            1 => ScaleMode::Expand,
+            _ => ScaleMode::Integer,
+        }
+    }
+
+    quickcheck! {
+        fn prop_camera_centre_is_origin(size: (u16, u16), centre: (i16, i16), zoom: u8) -> TestResult {
+            if size.0 == 0 || size.1 == 0 || zoom == 0 { return TestResult::discard() }
 
-        assert_eq!(0.5, pixel_to_unit(600, bound));
-        assert_eq!(0.0, pixel_to_unit(400, bound));
-        assert_eq!(-0.5, pixel_to_unit(200, bound));
+            let mut camera = Camera::new((size.0 as u32, size.1 as u32));
+            let centre = (centre.0 as f32, centre.1 as f32);
+            camera.look_at(centre);
+            camera.set_zoom(zoom as f32 / 16.0);
 
-        assert_eq!(1.5, pixel_to_unit(1000, bound));
-        assert_eq!(-1.5, pixel_to_unit(-200, bound));
+            let (x, y) = transform(camera.view_projection(), centre);
+            TestResult::from_bool(x.abs() <= 1e-3 && y.abs() <= 1e-3)
+        }
+
+        fn prop_screen_world_round_trip(size: (u16, u16), centre: (i16, i16), zoom: u8,
+                                        point: (u16, u16)) -> TestResult {
+            if size.0 == 0 || size.1 == 0 || zoom == 0 { return TestResult::discard() }
+
+            let mut camera = Camera::new((size.0 as u32, size.1 as u32));
+            camera.look_at((centre.0 as f32, centre.1 as f32));
+            camera.set_zoom(zoom as f32 / 16.0);
+
+            let screen_point = ((point.0 % size.0) as f32, (point.1 % size.1) as f32);
+            let round_trip = camera.world_to_screen(camera.screen_to_world(screen_point));
+
+            let close = |a: f32, b: f32| (a - b).abs() <= 0.1;
+            TestResult::from_bool(close(screen_point.0, round_trip.0) && close(screen_point.1, round_trip.1))
+        }
+
+        fn prop_viewport_fits_window(res: (u16, u16), window: (u16, u16), mode: u8) -> TestResult {
+            if res.0 == 0 || res.1 == 0 { return TestResult::discard() }
+
+            let window_size = (window.0 as u32, window.1 as u32);
+            let viewport = Viewport::new((res.0 as u32, res.1 as u32), scale_mode(mode));
+            let rect = viewport.rect(window_size);
+
+            TestResult::from_bool(rect.left + rect.width <= window_size.0
+                                  && rect.bottom + rect.height <= window_size.1)
+        }
+
+        fn prop_window_game_round_trip(res: (u16, u16), window: (u16, u16), hidpi: u8,
+                                       point: (u16, u16), mode: u8) -> TestResult {
+            if res.0 == 0 || res.1 == 0 { return TestResult::discard() }
+
+            let hidpi = (hidpi % 3 + 1) as u32;
+            let window_size = (window.0 as u32 * hidpi, window.1 as u32 * hidpi);
+            let viewport = Viewport::new((res.0 as u32, res.1 as u32), scale_mode(mode));
+
+            let rect = viewport.rect(window_size);
+            if rect.width == 0 || rect.height == 0 { return TestResult::discard() }
+
+            let game_point = ((point.0 % res.0) as f32, (point.1 % res.1) as f32);
+            let window_point = viewport.to_window(game_point, window_size);
+            let round_trip = viewport.to_game(window_point, window_size);
+
+            let close = |a: f32, b: f32| (a - b).abs() <= 1e-3 * a.abs().max(1.0);
+            TestResult::from_bool(close(game_point.0, round_trip.0) && close(game_point.1, round_trip.1))
+        }
     }
 }
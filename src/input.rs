@@ -0,0 +1,114 @@
+//! Maps keys to the actions they trigger, with bindings that can be
+//! remapped from the configuration file.
+
+use glium::glutin::VirtualKeyCode;
+use std::collections::HashMap;
+
+use policy;
+
+/// Something the player can ask the game to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    #[serde(rename = "quit")]
+    Quit,
+    #[serde(rename = "move_up")]
+    MoveUp,
+    #[serde(rename = "move_down")]
+    MoveDown,
+    #[serde(rename = "move_left")]
+    MoveLeft,
+    #[serde(rename = "move_right")]
+    MoveRight,
+}
+
+pub struct Keymap {
+    bindings: HashMap<VirtualKeyCode, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use glium::glutin::VirtualKeyCode::*;
+
+        let mut bindings = HashMap::new();
+        bindings.insert(Escape, Action::Quit);
+        bindings.insert(Up, Action::MoveUp);
+        bindings.insert(Down, Action::MoveDown);
+        bindings.insert(Left, Action::MoveLeft);
+        bindings.insert(Right, Action::MoveRight);
+
+        Keymap { bindings: bindings }
+    }
+}
+
+impl Keymap {
+    /// Builds a keymap from key names (as written in the configuration
+    /// file) to actions. Any action given a binding here loses its
+    /// default keys; the others keep them. Unknown key names are
+    /// reported and skipped unless `strict` is set.
+    pub fn from_bindings(overrides: &HashMap<String, Action>, strict: bool) -> Self {
+        let mut bindings: HashMap<VirtualKeyCode, Action> = Keymap::default().bindings.into_iter()
+            .filter(|&(_, action)| !overrides.values().any(|overridden| *overridden == action))
+            .collect();
+
+        for (name, action) in overrides {
+            match parse_key(name) {
+                Some(key) => { bindings.insert(key, *action); },
+                None => policy::degrade(strict, "Ignoring key binding", format!("unknown key `{}`", name)),
+            }
+        }
+
+        Keymap { bindings: bindings }
+    }
+
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings.get(&key).cloned()
+    }
+}
+
+macro_rules! parse_key_names {
+    ($name:expr, $($key:ident),*) => {{
+        $(if $name == stringify!($key) { return Some(VirtualKeyCode::$key) })*
+        None
+    }}
+}
+
+/// Parses a key from the name of its `VirtualKeyCode` variant, such as
+/// `A`, `Key1`, `Space` or `LShift`.
+pub fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    parse_key_names!(name,
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down,
+        Back, Return, Space, Tab,
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+        NumpadEnter, Add, Subtract, Multiply, Divide, Decimal,
+        Apostrophe, Backslash, Comma, Equals, Grave, LBracket, RBracket, Minus, Period, Semicolon, Slash,
+        LAlt, LControl, LShift, RAlt, RControl, RShift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glium::glutin::VirtualKeyCode;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(Some(VirtualKeyCode::W), parse_key("W"));
+        assert_eq!(Some(VirtualKeyCode::Key1), parse_key("Key1"));
+        assert_eq!(Some(VirtualKeyCode::LShift), parse_key("LShift"));
+        assert_eq!(None, parse_key("Hyper"));
+    }
+
+    #[test]
+    fn test_remapped_actions_lose_default_keys() {
+        let mut overrides = HashMap::new();
+        overrides.insert("W".to_owned(), Action::MoveUp);
+        let keymap = Keymap::from_bindings(&overrides, false);
+
+        assert_eq!(Some(Action::MoveUp), keymap.action(VirtualKeyCode::W));
+        assert_eq!(None, keymap.action(VirtualKeyCode::Up));
+        assert_eq!(Some(Action::MoveDown), keymap.action(VirtualKeyCode::Down));
+    }
+}
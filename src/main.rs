@@ -4,21 +4,46 @@
 #[macro_use] extern crate glium;
 #[macro_use] extern crate serde_derive;
 
+extern crate image;
 extern crate serde;
+extern crate serde_json;
 extern crate serde_yaml;
 
+#[cfg(test)] #[macro_use] extern crate quickcheck;
+
 mod app;
 mod config;
+#[cfg(feature = "dev-tools")]
+mod debug;
+mod doctor;
 mod graphics;
+mod input;
+mod policy;
+mod telemetry;
 
 use std::path::Path;
+use std::process;
 
 use app::App;
 
 fn main() {
     let config_file = Path::new("config.yml");
-    let mut config = config::load_from_file(config_file).ok().unwrap_or_default();
+
+    if config::requested_subcommand().as_ref().map(String::as_str) == Some("doctor") {
+        let healthy = doctor::run(config_file);
+        process::exit(if healthy { 0 } else { 1 });
+    }
+
+    let loaded = config::load_from_file(config_file);
+    let mut config = loaded.as_ref().ok().cloned().unwrap_or_default();
     config = config::apply_session_overrides(config);
 
-    App::from_config(config).run();
+    if let Err(err) = loaded {
+        policy::degrade(config.strict_mode, "Could not load config.yml, using defaults", err);
+    }
+
+    if let Err(err) = App::from_config(config).run() {
+        println!("Error: {}", err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,16 @@
+//! Decides whether a failure in a non-critical subsystem stops the game
+//! or is logged while the caller falls back to something that works.
+
+use std::fmt::Display;
+use std::thread;
+
+/// Reports a non-critical failure. In strict mode this panics, so that
+/// problems surface immediately during development; otherwise it logs
+/// a warning and the caller carries on with its fallback.
+pub fn degrade<E: Display>(strict: bool, context: &str, err: E) {
+    if strict && !thread::panicking() {
+        panic!("{}: {}", context, err);
+    }
+
+    println!("Warning: {}: {}", context, err);
+}
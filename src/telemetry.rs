@@ -0,0 +1,112 @@
+//! Opt-in, anonymous session statistics aggregated into a local JSON
+//! file. Nothing is sent anywhere; the file is left for the developer
+//! to inspect or share.
+
+use serde_json;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use policy;
+
+/// Upper bounds, in milliseconds, of the frame time buckets. Frames
+/// slower than the last bound are counted in a final open-ended bucket.
+const BUCKET_BOUNDS: [u64; 5] = [8, 17, 34, 50, 100];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrameTimeBucket {
+    pub up_to_ms: Option<u64>,
+    pub frames: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub gpu: String,
+    pub frame_times: Vec<FrameTimeBucket>,
+    pub crashed: bool,
+}
+
+impl Session {
+    pub fn new(gpu: String) -> Self {
+        let mut frame_times: Vec<FrameTimeBucket> = BUCKET_BOUNDS.iter()
+            .map(|&bound| FrameTimeBucket { up_to_ms: Some(bound), frames: 0 })
+            .collect();
+        frame_times.push(FrameTimeBucket { up_to_ms: None, frames: 0 });
+
+        Session {
+            gpu: gpu,
+            frame_times: frame_times,
+            crashed: true,
+        }
+    }
+
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        let millis = frame_time.as_secs() * 1_000 + frame_time.subsec_nanos() as u64 / 1_000_000;
+
+        for bucket in &mut self.frame_times {
+            if bucket.up_to_ms.map_or(true, |bound| millis < bound) {
+                bucket.frames += 1;
+                return;
+            }
+        }
+    }
+}
+
+/// Collects a `Session` and writes it out when dropped. The session is
+/// marked as crashed unless `finish` is called first, so a panic that
+/// unwinds past the game loop is still recorded.
+pub struct Telemetry {
+    session: Session,
+    path: PathBuf,
+    strict: bool,
+}
+
+impl Telemetry {
+    pub fn new<P: AsRef<Path>>(path: P, gpu: String, strict: bool) -> Self {
+        Telemetry {
+            session: Session::new(gpu),
+            path: path.as_ref().to_path_buf(),
+            strict: strict,
+        }
+    }
+
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        self.session.record_frame(frame_time);
+    }
+
+    pub fn finish(&mut self) {
+        self.session.crashed = false;
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        let result = File::create(&self.path)
+            .map_err(serde_json::Error::from)
+            .and_then(|mut file| serde_json::to_writer_pretty(&mut file, &self.session));
+
+        if let Err(err) = result {
+            let context = format!("Failed to write telemetry to {}", self.path.display());
+            policy::degrade(self.strict, &context, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_record_frame_buckets() {
+        let mut session = Session::new(String::new());
+
+        session.record_frame(Duration::from_millis(5));
+        session.record_frame(Duration::from_millis(16));
+        session.record_frame(Duration::from_millis(17));
+        session.record_frame(Duration::from_secs(2));
+
+        let frames: Vec<u64> = session.frame_times.iter().map(|bucket| bucket.frames).collect();
+        assert_eq!(vec![1, 1, 1, 0, 0, 1], frames);
+    }
+}